@@ -0,0 +1,162 @@
+//! Deciding what the camera sees, in clip space, the coordinates the camera's transformation matrix gives
+//! before dividing by `w`. A point is inside the view frustum when its `x`, `y` and `z` lie within `[-w, w]`.
+
+use nannou::glam::Vec4Swizzles;
+use nannou::prelude::*;
+
+
+/// Whether the point lies beyond the near plane, so dividing by its `w` doesn't mirror it or blow it up.
+pub fn is_in_front(clip: Vec4) -> bool {
+    clip.w > 0.0 && clip.z >= -clip.w
+}
+
+/// Whether the point lies inside the view frustum, widened by `padding` along x and y,
+/// as a fraction of the half width and height of the screen.
+pub fn is_in_frustum(clip: Vec4, padding: Vec2) -> bool {
+    let reach = clip.w * (Vec2::ONE + padding);
+
+    is_in_front(clip)
+        && clip.z <= clip.w
+        && clip.x.abs() <= reach.x
+        && clip.y.abs() <= reach.y
+}
+
+/// Where the point ends up on the screen, the area of `bounds`. Only meaningful for points in front of the camera.
+pub fn to_screen(clip: Vec4, bounds: Rect) -> Point2 {
+    bounds.xy() + clip.xy() / clip.w * bounds.wh() / 2.0
+}
+
+/// The screen position of a point drawn `size` big at a distance of 1 from a perspective camera,
+/// and the size it is drawn at where it is. `None` when it is culled, because no part of it can be seen.
+pub fn project_point(clip: Vec4, size: f32, bounds: Rect) -> Option<(Point2, f32)> {
+    if !is_in_front(clip) {
+        return None;
+    }
+
+    let size = size / clip.w;
+    let padding = Vec2::splat(size) * 2.0 / bounds.wh();
+    is_in_frustum(clip, padding).then(|| (to_screen(clip, bounds), size))
+}
+
+/// The part of the segment from `start` to `end` that lies beyond the near plane.
+/// `None` when it lies entirely in front of or behind the camera, or beside the frustum, so none of it can be seen.
+pub fn clip_segment(start: Vec4, end: Vec4) -> Option<(Vec4, Vec4)> {
+    // How far inside each plane of the frustum the endpoints are, negative when outside
+    let planes = |clip: Vec4| [
+        clip.w + clip.x,
+        clip.w - clip.x,
+        clip.w + clip.y,
+        clip.w - clip.y,
+        clip.w + clip.z,
+        clip.w - clip.z,
+    ];
+    let (start_distances, end_distances) = (planes(start), planes(end));
+
+    if start_distances.iter().zip(end_distances).any(|(start, end)| *start < 0.0 && end < 0.0) {
+        return None;
+    }
+
+    // The other planes can be left to the painter, only the near plane keeps `w` positive
+    let (start_near, end_near) = (start_distances[4], end_distances[4]);
+    let crossing = |from: Vec4, to: Vec4, from_near: f32, to_near: f32| from.lerp(to, from_near / (from_near - to_near));
+
+    match (start_near >= 0.0, end_near >= 0.0) {
+        (true, true) => Some((start, end)),
+        (true, false) => Some((start, crossing(start, end, start_near, end_near))),
+        (false, true) => Some((crossing(start, end, start_near, end_near), end)),
+        (false, false) => None,
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math_3d::Camera;
+
+    /// A camera at `z = 10` looking down the z axis at the origin.
+    fn camera() -> Camera {
+        Camera::new_perspective(vec3(0.0, 0.0, 10.0), vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0), 0.25 * PI, 1.0)
+    }
+
+    fn clip(position: Point3) -> Vec4 {
+        camera().get_transformation_matrix() * Vec4::from((position, 1.0))
+    }
+
+    fn bounds() -> Rect {
+        Rect::from_w_h(200.0, 100.0)
+    }
+
+    #[test]
+    fn points_inside_the_frustum_are_seen() {
+        let origin = clip(vec3(0.0, 0.0, 0.0));
+
+        assert!(is_in_front(origin));
+        assert!(is_in_frustum(origin, Vec2::ZERO));
+        assert_eq!(to_screen(origin, bounds()), vec2(0.0, 0.0));
+
+        let (center, size) = project_point(clip(vec3(1.0, 0.0, 0.0)), 5.0, bounds()).unwrap();
+        assert!(center.x > 0.0 && center.y.abs() < 1.0e-4);
+        assert!((size - 0.5).abs() < 1.0e-4, "a point 10 away is drawn a tenth as big, got {}", size);
+    }
+
+    #[test]
+    fn points_behind_the_camera_are_culled() {
+        for position in [vec3(0.0, 0.0, 20.0), vec3(1.0, 1.0, 10.0), vec3(0.0, 0.0, 9.95)] {
+            let behind = clip(position);
+
+            assert!(!is_in_front(behind), "{:?} is in front", position);
+            assert!(!is_in_frustum(behind, Vec2::ZERO), "{:?} is in the frustum", position);
+            assert_eq!(project_point(behind, 5.0, bounds()), None);
+        }
+    }
+
+    #[test]
+    fn points_beside_the_frustum_are_culled() {
+        let beside = clip(vec3(100.0, 0.0, 0.0));
+
+        assert!(is_in_front(beside));
+        assert!(!is_in_frustum(beside, Vec2::ZERO));
+        assert_eq!(project_point(beside, 5.0, bounds()), None);
+    }
+
+    #[test]
+    fn points_just_outside_the_screen_are_kept_while_their_size_reaches_into_it() {
+        // Just beyond the right edge, as seen from 10 away
+        let edge = clip(vec3(4.2, 0.0, 0.0));
+
+        assert!(!is_in_frustum(edge, Vec2::ZERO));
+        assert!(project_point(edge, 100.0, bounds()).is_some());
+        assert_eq!(project_point(edge, 1.0, bounds()), None);
+    }
+
+    #[test]
+    fn segments_inside_the_frustum_are_kept_whole() {
+        let (start, end) = (clip(vec3(-1.0, 0.0, 0.0)), clip(vec3(1.0, 1.0, 2.0)));
+
+        assert_eq!(clip_segment(start, end), Some((start, end)));
+    }
+
+    #[test]
+    fn segments_crossing_the_near_plane_are_cut_at_it() {
+        let (inside, behind) = (clip(vec3(1.0, 0.0, 0.0)), clip(vec3(1.0, 0.0, 20.0)));
+
+        let (start, end) = clip_segment(inside, behind).unwrap();
+        assert_eq!(start, inside);
+        assert!((end.z + end.w).abs() < 1.0e-4, "the cut end lies on the near plane, got {:?}", end);
+        assert!(end.w > 0.0);
+
+        let (start, end) = clip_segment(behind, inside).unwrap();
+        assert!((start.z + start.w).abs() < 1.0e-4, "the cut start lies on the near plane, got {:?}", start);
+        assert_eq!(end, inside);
+    }
+
+    #[test]
+    fn segments_outside_the_frustum_are_dropped() {
+        let behind = clip_segment(clip(vec3(0.0, 0.0, 11.0)), clip(vec3(1.0, 0.0, 20.0)));
+        let beside = clip_segment(clip(vec3(100.0, 0.0, 0.0)), clip(vec3(100.0, 5.0, -5.0)));
+
+        assert_eq!(behind, None);
+        assert_eq!(beside, None);
+    }
+}
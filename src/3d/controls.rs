@@ -1,4 +1,4 @@
-/// The controls for the camera
+//! The controls for the camera
 
 use nannou::prelude::*;
 use crate::math_3d::Camera;
@@ -42,47 +42,44 @@ impl CenteredCameraControls {
 
 impl CameraControls for CenteredCameraControls {
     fn event(&mut self, _app: &App, event: Event) {
-        match event {
-            Event::WindowEvent { simple: Some(inner_event), .. } => {
-                match inner_event {
-                    KeyPressed(key) => {
-                        match key {
-                            Key::Left => self.left_pressed = true,
-                            Key::Right => self.right_pressed = true,
-                            Key::Up => self.up_pressed = true,
-                            Key::Down => self.down_pressed = true,
-                            Key::PageUp => self.zoom_in_pressed = true,
-                            Key::PageDown => self.zoom_out_pressed = true,
-                            Key::A => self.left_pressed = true,
-                            Key::D => self.right_pressed = true,
-                            Key::W => self.up_pressed = true,
-                            Key::S => self.down_pressed = true,
-                            Key::Q => self.zoom_in_pressed = true,
-                            Key::E => self.zoom_out_pressed = true,
-                            _ => {}
-                        }
-                    },
-                    KeyReleased(key) => {
-                        match key {
-                            Key::Left => self.left_pressed = false,
-                            Key::Right => self.right_pressed = false,
-                            Key::Up => self.up_pressed = false,
-                            Key::Down => self.down_pressed = false,
-                            Key::PageUp => self.zoom_in_pressed = false,
-                            Key::PageDown => self.zoom_out_pressed = false,
-                            Key::A => self.left_pressed = false,
-                            Key::D => self.right_pressed = false,
-                            Key::W => self.up_pressed = false,
-                            Key::S => self.down_pressed = false,
-                            Key::Q => self.zoom_in_pressed = false,
-                            Key::E => self.zoom_out_pressed = false,
-                            _ => {}
-                        }
-                    },
-                    _ => {},
-                }
-            },
-            _ => {},
+        if let Event::WindowEvent { simple: Some(inner_event), .. } = event {
+            match inner_event {
+                KeyPressed(key) => {
+                    match key {
+                        Key::Left => self.left_pressed = true,
+                        Key::Right => self.right_pressed = true,
+                        Key::Up => self.up_pressed = true,
+                        Key::Down => self.down_pressed = true,
+                        Key::PageUp => self.zoom_in_pressed = true,
+                        Key::PageDown => self.zoom_out_pressed = true,
+                        Key::A => self.left_pressed = true,
+                        Key::D => self.right_pressed = true,
+                        Key::W => self.up_pressed = true,
+                        Key::S => self.down_pressed = true,
+                        Key::Q => self.zoom_in_pressed = true,
+                        Key::E => self.zoom_out_pressed = true,
+                        _ => {}
+                    }
+                },
+                KeyReleased(key) => {
+                    match key {
+                        Key::Left => self.left_pressed = false,
+                        Key::Right => self.right_pressed = false,
+                        Key::Up => self.up_pressed = false,
+                        Key::Down => self.down_pressed = false,
+                        Key::PageUp => self.zoom_in_pressed = false,
+                        Key::PageDown => self.zoom_out_pressed = false,
+                        Key::A => self.left_pressed = false,
+                        Key::D => self.right_pressed = false,
+                        Key::W => self.up_pressed = false,
+                        Key::S => self.down_pressed = false,
+                        Key::Q => self.zoom_in_pressed = false,
+                        Key::E => self.zoom_out_pressed = false,
+                        _ => {}
+                    }
+                },
+                _ => {},
+            }
         }
     }
 
@@ -169,13 +166,10 @@ impl MouseBasedCenteredCameraControls {
 
 impl CameraControls for MouseBasedCenteredCameraControls {
     fn event(&mut self, app: &App, event: Event) {
-        match event {
-            Event::Update(update) => {
-                self.mouse_position = app.mouse.position();
-                self.mouse_position_prev = self.mouse_position_prev + (self.mouse_position - self.mouse_position_prev) * update.since_last.as_secs_f32();
-                self.mouse_position_prev = (self.mouse_position_prev - self.mouse_position).clamp_length_max(2.0) + self.mouse_position;
-            },
-            _ => {},
+        if let Event::Update(update) = event {
+            self.mouse_position = app.mouse.position();
+            self.mouse_position_prev = self.mouse_position_prev + (self.mouse_position - self.mouse_position_prev) * update.since_last.as_secs_f32();
+            self.mouse_position_prev = (self.mouse_position_prev - self.mouse_position).clamp_length_max(2.0) + self.mouse_position;
         }
     }
 
@@ -0,0 +1,91 @@
+//! Conveying depth when drawing points: painting them from back to front,
+//! and fading and shrinking them with their distance across the part of the scene they move in.
+
+use nannou::color::Alpha;
+use nannou::prelude::*;
+use crate::math_3d::Camera;
+use crate::palette::mix;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct DepthCueOptions {
+    /// How far points at the back of the scene fade into the background color, from 0 to 1.
+    pub fog: f32,
+    /// How much smaller and fainter points at the back of the scene are drawn, from 0 to 1.
+    pub attenuation: f32,
+}
+
+impl DepthCueOptions {
+    /// The parameters for depth cueing, with the scene's defaults.
+    pub fn parameters(defaults: DepthCueOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::float("depth-fog", "How far the farthest points fade into the background color, 0 doesn't fade them", defaults.fog)
+                .with_range(0.0, 1.0),
+            Parameter::float("depth-attenuation", "How much smaller and fainter the farthest points are drawn, 0 draws them like the nearest", defaults.attenuation)
+                .with_range(0.0, 1.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            fog: values.float("depth-fog"),
+            attenuation: values.float("depth-attenuation"),
+        }
+    }
+}
+
+
+/// Applies the depth cueing options to points seen by a camera.
+///
+/// The cueing spans the depths of a sphere around the scene, rather than the camera's near and far planes,
+/// so fitting it to the scene doesn't cull the points that stray outside.
+#[derive(Copy, Clone, Debug)]
+pub struct DepthCue {
+    options: DepthCueOptions,
+    /// The depth of the front of the sphere, where points are drawn as they are.
+    near: f32,
+    /// The depth of the back of the sphere, where points are cued the most.
+    far: f32,
+    background: Rgb,
+}
+
+impl DepthCue {
+    /// Cues points over the sphere at `center` with `radius`, points in front of or behind it are cued like its front or back.
+    pub fn new(camera: &Camera, center: Point3, radius: f32, options: DepthCueOptions, background: Srgb<u8>) -> Self {
+        let depth = camera.depth(center);
+
+        Self {
+            options,
+            near: depth - radius,
+            far: depth + radius,
+            background: background.into_format(),
+        }
+    }
+
+    /// The color and size a point `depth` in front of the camera is drawn with.
+    pub fn apply(&self, depth: f32, color: Alpha<Rgb, f32>, size: f32) -> (Alpha<Rgb, f32>, f32) {
+        let fraction = ((depth - self.near) / (self.far - self.near).max(f32::EPSILON)).clamp(0.0, 1.0);
+        let attenuation = 1.0 - self.options.attenuation * fraction;
+
+        let color = Alpha {
+            color: mix(color.color, self.background, self.options.fog * fraction),
+            alpha: color.alpha * attenuation,
+        };
+
+        (color, size * attenuation)
+    }
+}
+
+
+/// The indices of the points with their depths, from the farthest from the camera to the nearest,
+/// the order translucent points have to be painted in to cover each other correctly.
+pub fn back_to_front(camera: &Camera, points: impl Iterator<Item = Point3>) -> Vec<(usize, f32)> {
+    let mut order = points
+        .map(|point| camera.depth(point))
+        .enumerate()
+        .collect::<Vec<_>>();
+    order.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    order
+}
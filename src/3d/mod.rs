@@ -1,10 +1,15 @@
-use std::fmt::Debug;
-use nannou::glam::Vec4Swizzles;
 use nannou::prelude::*;
-use crate::math_3d::projection::{OrthographicProjection, PerspectiveProjection, Projection};
+use serde_json::{json, Value};
+use crate::math_3d::projection::{OrthographicProjection, PerspectiveProjection, Projection, projection_from_snapshot};
+use crate::snapshot::{field, Snapshot, vec3_field};
 
 pub mod projection;
 pub mod controls;
+pub mod clip;
+pub mod depth;
+
+/// How much further than asked `Camera::fit_far_plane` puts the plane, so points on the sphere aren't culled by rounding.
+const FAR_PLANE_PADDING: f32 = 1.01;
 
 /// For turning 3D coordinates to screen space
 /// The screen space is defined as the area of (x, y) values with range `[-1, 1]`
@@ -68,6 +73,17 @@ impl Camera {
         projection_matrix * view_matrix
     }
 
+    /// How far the point lies in front of the camera, along its view direction.
+    pub fn depth(&self, point: Point3) -> f32 {
+        (point - self.position).dot(self.view_direction.normalize())
+    }
+
+    /// Moves the far plane onto the back of the sphere, so nothing in it is culled however far away the camera is.
+    pub fn fit_far_plane(&mut self, center: Point3, radius: f32) {
+        let z_far = self.depth(center) + radius * FAR_PLANE_PADDING;
+        self.z_far(z_far.max(self.get_z_near() * 2.0));
+    }
+
     pub fn get_z_near(&self) -> f32 {
         self.projection.get_z_near()
     }
@@ -92,3 +108,32 @@ impl Camera {
         self.projection.aspect_ratio(aspect_ratio);
     }
 }
+
+impl Snapshot for Camera {
+    fn snapshot(&self) -> Value {
+        json!({
+            "position": self.position.to_array(),
+            "view_direction": self.view_direction.to_array(),
+            "up": self.up.to_array(),
+            "projection": self.projection.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        *self = Camera::from_snapshot(snapshot)?;
+
+        Ok(())
+    }
+}
+
+impl Camera {
+    /// Rebuilds a camera written by `Snapshot::snapshot`.
+    pub fn from_snapshot(snapshot: &Value) -> Result<Self, String> {
+        Ok(Self {
+            position: vec3_field(snapshot, "position")?,
+            view_direction: vec3_field(snapshot, "view_direction")?,
+            up: vec3_field(snapshot, "up")?,
+            projection: projection_from_snapshot(field(snapshot, "projection")?)?,
+        })
+    }
+}
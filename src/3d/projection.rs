@@ -1,5 +1,7 @@
 use nannou::glam::Vec4Swizzles;
 use nannou::prelude::*;
+use serde_json::{json, Value};
+use crate::snapshot::{f32_field, field};
 
 
 const DEFAULT_Z_NEAR: f32 = 0.1;
@@ -25,6 +27,32 @@ pub trait Projection {
 
     fn get_aspect_ratio(&self) -> f32;
     fn aspect_ratio(&mut self, aspect_ratio: f32);
+
+    /// Writes the projection, along with its kind, so `projection_from_snapshot` can rebuild it.
+    fn snapshot(&self) -> Value;
+}
+
+/// Rebuilds a projection written by `Projection::snapshot`.
+pub fn projection_from_snapshot(snapshot: &Value) -> Result<Box<dyn Projection>, String> {
+    let z_near = f32_field(snapshot, "z_near")?;
+    let z_far = f32_field(snapshot, "z_far")?;
+    let aspect_ratio = f32_field(snapshot, "aspect_ratio")?;
+
+    match field(snapshot, "kind")?.as_str() {
+        Some("orthographic") => Ok(Box::new(OrthographicProjection {
+            size: f32_field(snapshot, "size")?,
+            z_near,
+            z_far,
+            aspect_ratio,
+        })),
+        Some("perspective") => Ok(Box::new(PerspectiveProjection {
+            fov_y_radians: f32_field(snapshot, "fov_y_radians")?,
+            z_near,
+            z_far,
+            aspect_ratio,
+        })),
+        _ => Err(format!("Unknown projection kind {}", field(snapshot, "kind")?)),
+    }
 }
 
 
@@ -44,8 +72,8 @@ impl OrthographicProjection {
     pub fn new(size: f32, aspect_ratio: f32) -> Self {
         Self {
             size,
-            z_near: 0.1,
-            z_far: 1000.0,
+            z_near: DEFAULT_Z_NEAR,
+            z_far: DEFAULT_Z_FAR,
             aspect_ratio,
         }
     }
@@ -86,6 +114,16 @@ impl Projection for OrthographicProjection {
     fn aspect_ratio(&mut self, aspect_ratio: f32) {
         self.aspect_ratio = aspect_ratio;
     }
+
+    fn snapshot(&self) -> Value {
+        json!({
+            "kind": "orthographic",
+            "size": self.size,
+            "z_near": self.z_near,
+            "z_far": self.z_far,
+            "aspect_ratio": self.aspect_ratio,
+        })
+    }
 }
 
 pub struct PerspectiveProjection {
@@ -99,8 +137,8 @@ impl PerspectiveProjection {
     pub fn new(fov: f32, aspect_ratio: f32) -> Self {
         Self {
             fov_y_radians: fov,
-            z_near: 0.1,
-            z_far: 1000.0,
+            z_near: DEFAULT_Z_NEAR,
+            z_far: DEFAULT_Z_FAR,
             aspect_ratio,
         }
     }
@@ -139,4 +177,13 @@ impl Projection for PerspectiveProjection {
     fn aspect_ratio(&mut self, aspect_ratio: f32) {
         self.aspect_ratio = aspect_ratio;
     }
+    fn snapshot(&self) -> Value {
+        json!({
+            "kind": "perspective",
+            "fov_y_radians": self.fov_y_radians,
+            "z_near": self.z_near,
+            "z_far": self.z_far,
+            "aspect_ratio": self.aspect_ratio,
+        })
+    }
 }
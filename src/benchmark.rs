@@ -0,0 +1,133 @@
+//! Timing the Perlin flow simulation with the noise sampled for every particle against the cached flow grid.
+
+use std::time::{Duration, Instant};
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use nannou::prelude::*;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::perlin_flow::{PerlinFlowOptions, PerlinFlowSimulation};
+use crate::simulation::{seeded_rng, Simulation};
+
+/// The grid resolution compared against when `--grid-resolution` is left at 0.
+const DEFAULT_GRID_RESOLUTION: usize = 128;
+
+
+#[derive(Clone, Debug)]
+pub struct BenchmarkOptions {
+    /// The amount of steps every run simulates.
+    pub steps: u64,
+    /// The frame rate the simulation is advanced at.
+    pub fps: f32,
+    pub scene: PerlinFlowOptions,
+}
+
+impl BenchmarkOptions {
+    /// The `benchmark` subcommand, which takes the parameters of the Perlin flow scene.
+    pub fn command() -> Command {
+        Command::new("benchmark")
+            .about("Times the Perlin flow simulation sampling the noise directly and through the flow grid")
+            .arg(Arg::new("steps")
+                .long("steps")
+                .help("The amount of steps every run simulates")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("200"))
+            .arg(Arg::new("fps")
+                .long("fps")
+                .help("The frame rate the simulation is advanced at")
+                .value_parser(value_parser!(f32))
+                .default_value("60"))
+            .args(PerlinFlowOptions::parameters().iter().map(Parameter::arg))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Result<Self, String> {
+        let values = ParameterValues::from_matches(&PerlinFlowOptions::parameters(), matches)?;
+
+        Ok(Self {
+            steps: *matches.get_one::<u64>("steps").unwrap(),
+            fps: *matches.get_one::<f32>("fps").unwrap(),
+            scene: PerlinFlowOptions::from_parameters(&values),
+        })
+    }
+}
+
+
+/// How long one run took.
+#[derive(Clone, Debug)]
+pub struct BenchmarkRun {
+    pub label: String,
+    pub duration: Duration,
+    /// The amount of particles times the amount of steps.
+    pub particle_steps: u64,
+}
+
+impl BenchmarkRun {
+    pub fn particle_steps_per_second(&self) -> f64 {
+        self.particle_steps as f64 / self.duration.as_secs_f64()
+    }
+}
+
+/// Runs the simulation with direct sampling and then with the grid, from the same seed so both move the same particles.
+pub fn benchmark(options: &BenchmarkOptions) -> Result<Vec<BenchmarkRun>, String> {
+    if options.fps.is_nan() || options.fps <= 0.0 {
+        return Err(format!("The frame rate must be positive, got {}", options.fps));
+    }
+
+    let (seed, _) = seeded_rng(options.scene.seed);
+    let grid_resolution = match options.scene.grid_resolution {
+        0 => DEFAULT_GRID_RESOLUTION,
+        resolution => resolution,
+    };
+
+    let runs = [
+        ("direct sampling".to_string(), 0),
+        (format!("{0}x{0} grid", grid_resolution), grid_resolution),
+    ];
+
+    Ok(runs.into_iter()
+        .map(|(label, grid_resolution)| {
+            let scene = PerlinFlowOptions {
+                seed: Some(seed),
+                grid_resolution,
+                ..options.scene
+            };
+            time_run(label, &scene, options)
+        })
+        .collect())
+}
+
+fn time_run(label: String, scene: &PerlinFlowOptions, options: &BenchmarkOptions) -> BenchmarkRun {
+    let (_, mut rng) = seeded_rng(scene.seed);
+    let bounds = Rect::from_w_h(scene.window.width as f32, scene.window.height as f32);
+    let mut simulation = PerlinFlowSimulation::new(&mut rng, scene, bounds);
+    let dt = 1.0 / options.fps;
+
+    let start = Instant::now();
+    for _ in 0..options.steps {
+        simulation.step(dt);
+    }
+
+    BenchmarkRun {
+        label,
+        duration: start.elapsed(),
+        particle_steps: simulation.particles().len() as u64 * options.steps,
+    }
+}
+
+/// A line per run, followed by how much faster the last run was than the first.
+pub fn describe_runs(runs: &[BenchmarkRun]) -> String {
+    let mut description = String::new();
+
+    for run in runs {
+        description += &format!(
+            "{}: {:.3} s, {:.0} particle steps/s\n",
+            run.label,
+            run.duration.as_secs_f64(),
+            run.particle_steps_per_second(),
+        );
+    }
+    if let [first, .., last] = runs {
+        description += &format!("Speedup: {:.2}x\n", last.particle_steps_per_second() / first.particle_steps_per_second());
+    }
+
+    description
+}
@@ -0,0 +1,156 @@
+//! Coloring particles by one of their attributes, mapped through a palette.
+
+use nannou::prelude::*;
+use crate::palette::{Interpolation, Palette};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// Particles living forever reach the end of the palette after this many seconds when colored by age.
+pub const FOREVER_AGE_SPAN: f32 = 60.0;
+
+
+/// The particle attribute that picks a particle's color from the palette.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorAttribute {
+    /// Every particle has the scene's particle color.
+    Constant,
+    /// The direction the particle moves in, going through the palette once per turn.
+    Angle,
+    Speed,
+    Age,
+    /// The distance from the center of the scene.
+    Distance,
+    /// The height, which is the z coordinate in 3D scenes and the y coordinate in 2D ones.
+    Height,
+}
+
+impl ColorAttribute {
+    pub const ALL: [ColorAttribute; 6] = [
+        ColorAttribute::Constant,
+        ColorAttribute::Angle,
+        ColorAttribute::Speed,
+        ColorAttribute::Age,
+        ColorAttribute::Distance,
+        ColorAttribute::Height,
+    ];
+
+    /// The names of all attributes, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "constant",
+        "angle",
+        "speed",
+        "age",
+        "distance",
+        "height",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|attribute| attribute == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+
+/// A particle's attributes, each scaled by its scene to `[0, 1]` over the range it usually covers.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct ColorSample {
+    /// The direction of the velocity in radians.
+    pub angle: f32,
+    pub speed: f32,
+    pub age: f32,
+    pub distance: f32,
+    pub height: f32,
+}
+
+impl ColorSample {
+    /// The position along the palette for the attribute, `None` for `Constant`.
+    pub fn value(&self, attribute: ColorAttribute) -> Option<f32> {
+        let value = match attribute {
+            ColorAttribute::Constant => return None,
+            ColorAttribute::Angle => (self.angle / TAU).rem_euclid(1.0),
+            ColorAttribute::Speed => self.speed,
+            ColorAttribute::Age => self.age,
+            ColorAttribute::Distance => self.distance,
+            ColorAttribute::Height => self.height,
+        };
+
+        Some(value.clamp(0.0, 1.0))
+    }
+}
+
+/// The age of a particle as a fraction of its lifetime, or of `FOREVER_AGE_SPAN` when it lives forever.
+pub fn age_fraction(age: f32, lifetime: Option<f32>) -> f32 {
+    age / lifetime.unwrap_or(FOREVER_AGE_SPAN)
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ColormapOptions {
+    pub attribute: ColorAttribute,
+    /// The palette the colors are picked from, whose background the scene is drawn onto.
+    pub palette: Palette,
+    pub interpolation: Interpolation,
+    /// Quantizes the palette to this many colors, it stays smooth at 0.
+    /// A few steps suit plotting, which needs a pen for every color.
+    pub steps: u32,
+}
+
+impl Default for ColormapOptions {
+    fn default() -> Self {
+        Self::with_palette("dusk")
+    }
+}
+
+impl ColormapOptions {
+    /// The default options with the built-in palette of the name.
+    pub fn with_palette(name: &str) -> Self {
+        Self {
+            attribute: ColorAttribute::Constant,
+            palette: Palette::named(name).unwrap(),
+            interpolation: Interpolation::Oklab,
+            steps: 0,
+        }
+    }
+
+    /// The parameters for the colormap, with the scene's defaults.
+    pub fn parameters(defaults: ColormapOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::choice("color-by", "The particle attribute that picks its color from the palette", ColorAttribute::NAMES, defaults.attribute.name()),
+            Parameter::palette("palette", "The palette the particle colors and the background are picked from", defaults.palette),
+            Parameter::choice("interpolation", "The color space gradient palettes blend their colors in", Interpolation::NAMES, defaults.interpolation.name()),
+            Parameter::integer("color-steps", "Quantizes the palette to this many colors, 0 keeps it smooth", defaults.steps as i64)
+                .with_range(0.0, 256.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            attribute: ColorAttribute::from_name(values.choice("color-by")).unwrap(),
+            palette: values.palette("palette"),
+            interpolation: Interpolation::from_name(values.choice("interpolation")).unwrap(),
+            steps: values.integer("color-steps") as u32,
+        }
+    }
+
+    /// The color for a particle with the given attributes, `None` when particles keep their own color.
+    pub fn color(&self, sample: &ColorSample) -> Option<Rgb> {
+        sample.value(self.attribute).map(|value| self.palette_color(value))
+    }
+
+    /// The color at `t` along the palette, quantized to the steps.
+    pub fn palette_color(&self, t: f32) -> Rgb {
+        let t = match self.steps {
+            0 => t,
+            1 => 0.0,
+            steps => (t * steps as f32).floor().min(steps as f32 - 1.0) / (steps as f32 - 1.0),
+        };
+
+        self.palette.color(t, self.interpolation)
+    }
+}
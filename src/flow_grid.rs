@@ -0,0 +1,61 @@
+//! A flow field sampled once on a grid, so particles can look up forces without sampling noise.
+
+use nannou::prelude::*;
+
+/// The forces of a field at the nodes of a grid stretched over some bounds.
+/// Positions between the nodes are blended bilinearly, positions outside take the force at the nearest edge.
+#[derive(Clone, Debug)]
+pub struct FlowGrid {
+    /// The number of cells along each axis, there is one more node than cells.
+    resolution: usize,
+    bounds: Rect,
+    /// The forces at the nodes, row by row from the bottom left.
+    forces: Vec<Vec2>,
+}
+
+impl FlowGrid {
+    /// Samples the field at every node of a grid with `resolution` cells along each axis.
+    pub fn new<F>(resolution: usize, bounds: Rect, field: F) -> Self
+        where
+            F: Fn(Point2) -> Vec2
+    {
+        let resolution = resolution.max(1);
+        let cell_size = bounds.wh() / resolution as f32;
+
+        let forces = (0..=resolution)
+            .flat_map(|row| (0..=resolution).map(move |column| (column, row)))
+            .map(|(column, row)| field(bounds.bottom_left() + vec2(column as f32, row as f32) * cell_size))
+            .collect();
+
+        Self {
+            resolution,
+            bounds,
+            forces,
+        }
+    }
+
+    pub fn resolution(&self) -> usize {
+        self.resolution
+    }
+
+    pub fn bounds(&self) -> Rect {
+        self.bounds
+    }
+
+    /// The force at the position, blended from the four surrounding nodes.
+    pub fn sample(&self, position: Point2) -> Vec2 {
+        let last = self.resolution as f32;
+        let cell = ((position - self.bounds.bottom_left()) / self.bounds.wh() * last).clamp(Vec2::ZERO, Vec2::splat(last));
+
+        // The far edge belongs to the last cell, so the node after it always exists
+        let column = (cell.x.floor() as usize).min(self.resolution - 1);
+        let row = (cell.y.floor() as usize).min(self.resolution - 1);
+        let fraction = cell - vec2(column as f32, row as f32);
+
+        let node = |column: usize, row: usize| self.forces[row * (self.resolution + 1) + column];
+        let bottom = node(column, row).lerp(node(column + 1, row), fraction.x);
+        let top = node(column, row + 1).lerp(node(column + 1, row + 1), fraction.x);
+
+        bottom.lerp(top, fraction.y)
+    }
+}
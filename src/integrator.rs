@@ -0,0 +1,368 @@
+//! Numerical integration of the ordinary differential equations particles follow,
+//! from the velocity of a point on an attractor to the force a flow field pushes a particle with.
+
+use nannou::prelude::*;
+use serde_json::{json, Value};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// How many times the adaptive integrator may retry or split a step, before it takes the step it has.
+const MAX_ADAPTIVE_STEPS: usize = 1000;
+
+/// The smallest and largest factor the adaptive integrator changes its step size by at once.
+const MIN_STEP_FACTOR: f32 = 0.2;
+const MAX_STEP_FACTOR: f32 = 5.0;
+
+/// The Dormand–Prince tableau, https://en.wikipedia.org/wiki/Dormand%E2%80%93Prince_method
+const DOPRI_A: [[f32; 6]; 6] = [
+    [1.0 / 5.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+    [3.0 / 40.0, 9.0 / 40.0, 0.0, 0.0, 0.0, 0.0],
+    [44.0 / 45.0, -56.0 / 15.0, 32.0 / 9.0, 0.0, 0.0, 0.0],
+    [19372.0 / 6561.0, -25360.0 / 2187.0, 64448.0 / 6561.0, -212.0 / 729.0, 0.0, 0.0],
+    [9017.0 / 3168.0, -355.0 / 33.0, 46732.0 / 5247.0, 49.0 / 176.0, -5103.0 / 18656.0, 0.0],
+    [35.0 / 384.0, 0.0, 500.0 / 1113.0, 125.0 / 192.0, -2187.0 / 6784.0, 11.0 / 84.0],
+];
+
+/// The weights of the fifth order solution minus those of the embedded fourth order one, which estimate the error.
+const DOPRI_ERROR: [f32; 7] = [
+    71.0 / 57600.0,
+    0.0,
+    -71.0 / 16695.0,
+    71.0 / 1920.0,
+    -17253.0 / 339200.0,
+    22.0 / 525.0,
+    -1.0 / 40.0,
+];
+
+
+/// The state of a system of differential equations, and its derivative, which has the same shape.
+pub trait State: Copy {
+    /// The state `h` time along the derivative, `self + derivative * h`.
+    fn add_scaled(self, derivative: Self, h: f32) -> Self;
+
+    /// The largest absolute value of any of the components.
+    fn norm(self) -> f32;
+}
+
+impl State for Vec2 {
+    fn add_scaled(self, derivative: Self, h: f32) -> Self {
+        self + derivative * h
+    }
+
+    fn norm(self) -> f32 {
+        self.abs().max_element()
+    }
+}
+
+impl State for Vec3 {
+    fn add_scaled(self, derivative: Self, h: f32) -> Self {
+        self + derivative * h
+    }
+
+    fn norm(self) -> f32 {
+        self.abs().max_element()
+    }
+}
+
+/// A position with a velocity, for particles that are pushed by forces.
+impl<A: State, B: State> State for (A, B) {
+    fn add_scaled(self, derivative: Self, h: f32) -> Self {
+        (self.0.add_scaled(derivative.0, h), self.1.add_scaled(derivative.1, h))
+    }
+
+    fn norm(self) -> f32 {
+        self.0.norm().max(self.1.norm())
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IntegratorKind {
+    /// Follows the derivative at the start of the step, first order.
+    Euler,
+    /// Follows the derivative halfway along the Euler step, second order.
+    Midpoint,
+    /// The classic fourth order Runge–Kutta method.
+    Rk4,
+    /// The Dormand–Prince method, which splits the step to keep its error within the tolerance.
+    Rk45,
+}
+
+impl IntegratorKind {
+    pub const ALL: [IntegratorKind; 4] = [
+        IntegratorKind::Euler,
+        IntegratorKind::Midpoint,
+        IntegratorKind::Rk4,
+        IntegratorKind::Rk45,
+    ];
+
+    /// The names of all kinds, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "euler",
+        "midpoint",
+        "rk4",
+        "rk45",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|kind| kind == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct IntegratorOptions {
+    pub kind: IntegratorKind,
+    /// The error the adaptive integrator allows per step, relative to the size of the state once it is larger than 1.
+    pub tolerance: f32,
+}
+
+impl Default for IntegratorOptions {
+    fn default() -> Self {
+        Self {
+            kind: IntegratorKind::Euler,
+            tolerance: 1.0e-4,
+        }
+    }
+}
+
+impl IntegratorOptions {
+    /// The parameters for the integrator, with the scene's defaults.
+    pub fn parameters(defaults: IntegratorOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::choice("integrator", "How the particles' equations of motion are solved", IntegratorKind::NAMES, defaults.kind.name()),
+            Parameter::float("integrator-tolerance", "The error the rk45 integrator allows per step", defaults.tolerance)
+                .with_range(1.0e-9, 1.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            kind: IntegratorKind::from_name(values.choice("integrator")).unwrap(),
+            tolerance: values.float("integrator-tolerance"),
+        }
+    }
+
+    /// The options by parameter name, for checking a snapshot against.
+    pub fn settings(&self) -> Value {
+        json!({
+            "integrator": self.kind.name(),
+            "integrator-tolerance": self.tolerance,
+        })
+    }
+
+    /// The state after `dt` time, following the derivative of the system, which doesn't depend on time itself.
+    pub fn step<S, F>(&self, state: S, dt: f32, derivative: F) -> S
+        where
+            S: State,
+            F: Fn(S) -> S
+    {
+        match self.kind {
+            IntegratorKind::Euler => state.add_scaled(derivative(state), dt),
+            IntegratorKind::Midpoint => {
+                let halfway = state.add_scaled(derivative(state), dt / 2.0);
+                state.add_scaled(derivative(halfway), dt)
+            },
+            IntegratorKind::Rk4 => {
+                let k1 = derivative(state);
+                let k2 = derivative(state.add_scaled(k1, dt / 2.0));
+                let k3 = derivative(state.add_scaled(k2, dt / 2.0));
+                let k4 = derivative(state.add_scaled(k3, dt));
+
+                state
+                    .add_scaled(k1, dt / 6.0)
+                    .add_scaled(k2, dt / 3.0)
+                    .add_scaled(k3, dt / 3.0)
+                    .add_scaled(k4, dt / 6.0)
+            },
+            IntegratorKind::Rk45 => self.adaptive_step(state, dt, derivative),
+        }
+    }
+
+    /// Covers `dt` in as many Dormand–Prince steps as it takes to keep the error of each within the tolerance.
+    fn adaptive_step<S, F>(&self, mut state: S, dt: f32, derivative: F) -> S
+        where
+            S: State,
+            F: Fn(S) -> S
+    {
+        let mut time = 0.0;
+        let mut h = dt;
+
+        for _ in 0..MAX_ADAPTIVE_STEPS {
+            if time >= dt {
+                return state;
+            }
+            h = h.min(dt - time);
+
+            let (next, error) = dormand_prince(state, h, &derivative);
+            if !error.is_finite() {
+                // The step ran into something that blew up, a smaller one may stay clear of it
+                h *= MIN_STEP_FACTOR;
+                continue;
+            }
+
+            let allowed = self.tolerance * state.norm().max(1.0);
+            if error <= allowed {
+                state = next;
+                time += h;
+            }
+
+            // The error of a fifth order step shrinks with the fifth power of the step size
+            let factor = if error > 0.0 { 0.9 * (allowed / error).powf(0.2) } else { MAX_STEP_FACTOR };
+            h *= factor.clamp(MIN_STEP_FACTOR, MAX_STEP_FACTOR);
+        }
+
+        // Out of retries, the rest of the step is taken at once
+        dormand_prince(state, dt - time, &derivative).0
+    }
+}
+
+/// A Dormand–Prince step of `h`, giving the fifth order solution and an estimate of its error.
+fn dormand_prince<S, F>(state: S, h: f32, derivative: &F) -> (S, f32)
+    where
+        S: State,
+        F: Fn(S) -> S
+{
+    let mut k = [derivative(state); 7];
+    for stage in 1..7 {
+        let point = (0..stage).fold(state, |point, i| point.add_scaled(k[i], h * DOPRI_A[stage - 1][i]));
+        k[stage] = derivative(point);
+    }
+
+    // The last stage is taken at the fifth order solution, so its weights are the last row of the tableau
+    let next = (0..6).fold(state, |next, i| next.add_scaled(k[i], h * DOPRI_A[5][i]));
+    // The states have no zero of their own, summing the difference onto the state instead would round it away
+    let zero = k[0].add_scaled(k[0], -1.0);
+    let error = (0..7).fold(zero, |difference, i| difference.add_scaled(k[i], h * DOPRI_ERROR[i])).norm();
+
+    (next, error)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+
+    /// The Lorenz system with its classic coefficients.
+    fn lorenz(position: Vec3) -> Vec3 {
+        vec3(
+            10.0 * (position.y - position.x),
+            position.x * (28.0 - position.z) - position.y,
+            position.x * position.y - 8.0 / 3.0 * position.z,
+        )
+    }
+
+    fn integrator(kind: IntegratorKind) -> IntegratorOptions {
+        IntegratorOptions { kind, tolerance: 1.0e-6 }
+    }
+
+    /// The error of following `y' = -y` from 1 for a time of 1 in steps of `dt`.
+    fn decay_error(kind: IntegratorKind, dt: f32) -> f32 {
+        let steps = (1.0 / dt).round() as usize;
+        let mut state = vec2(1.0, 0.0);
+        for _ in 0..steps {
+            state = integrator(kind).step(state, dt, |state| -state);
+        }
+
+        (state.x - (-1.0f32).exp()).abs()
+    }
+
+    /// The error of following a harmonic oscillator, `x'' = -x`, through one period in steps of `dt`.
+    fn oscillator_error(kind: IntegratorKind, steps: usize) -> f32 {
+        let dt = TAU / steps as f32;
+        let mut state = (vec2(1.0, 0.0), vec2(0.0, 1.0));
+        for _ in 0..steps {
+            state = integrator(kind).step(state, dt, |(position, velocity): (Vec2, Vec2)| (velocity, -position));
+        }
+
+        state.0.distance(vec2(1.0, 0.0)).max(state.1.distance(vec2(0.0, 1.0)))
+    }
+
+    /// The order of convergence, from the errors at a step size and at half of it.
+    fn order(error: f32, half_step_error: f32) -> f32 {
+        (error / half_step_error).log2()
+    }
+
+    #[test]
+    fn fixed_step_methods_converge_with_their_order() {
+        let orders = [
+            (IntegratorKind::Euler, 1.0),
+            (IntegratorKind::Midpoint, 2.0),
+            (IntegratorKind::Rk4, 4.0),
+        ];
+
+        for (kind, expected) in orders {
+            // Large steps, since the error of RK4 at small ones drowns in rounding
+            let decay = order(decay_error(kind, 0.5), decay_error(kind, 0.25));
+            let oscillator = order(oscillator_error(kind, 32), oscillator_error(kind, 64));
+
+            assert!((decay - expected).abs() < 0.5, "{} converges with order {} on decay", kind.name(), decay);
+            assert!((oscillator - expected).abs() < 0.5, "{} converges with order {} on the oscillator", kind.name(), oscillator);
+        }
+    }
+
+    #[test]
+    fn higher_orders_are_more_accurate() {
+        let errors = IntegratorKind::ALL.map(|kind| oscillator_error(kind, 32));
+
+        assert!(errors[0] > errors[1] && errors[1] > errors[2], "errors {:?}", errors);
+        assert!(errors[3] < 1.0e-4, "rk45 is off by {}", errors[3]);
+    }
+
+    #[test]
+    fn rk45_meets_its_tolerance_in_a_single_large_step() {
+        let state = integrator(IntegratorKind::Rk45).step(vec2(1.0, 0.0), 1.0, |state| -state);
+
+        assert!((state.x - (-1.0f32).exp()).abs() < 1.0e-5, "got {}", state.x);
+    }
+
+    #[test]
+    fn lorenz_matches_reference_point() {
+        // From (1, 1, 1) after half a unit of time, integrated in double precision with tiny RK4 steps
+        let reference = vec3(1.198273, -8.867198, 32.45474);
+        let tolerances = [
+            (IntegratorKind::Midpoint, 1.0e-1),
+            (IntegratorKind::Rk4, 1.0e-2),
+            (IntegratorKind::Rk45, 1.0e-2),
+        ];
+
+        for (kind, tolerance) in tolerances {
+            let mut position = vec3(1.0, 1.0, 1.0);
+            for _ in 0..500 {
+                position = integrator(kind).step(position, 0.001, lorenz);
+            }
+
+            assert!(position.distance(reference) < tolerance, "{} ends at {:?}", kind.name(), position);
+        }
+    }
+
+    #[test]
+    fn rk45_retries_steps_that_blow_up() {
+        // The state settles from 1 towards 1.5, and the derivative blows up past it,
+        // where the stages of a first attempt as large as the whole step overshoot to
+        let calls = Cell::new(0);
+        let blow_ups = Cell::new(0);
+        let state = integrator(IntegratorKind::Rk45).step(vec2(1.0, 0.0), 1.0, |state: Vec2| {
+            calls.set(calls.get() + 1);
+            if state.x > 1.5 {
+                blow_ups.set(blow_ups.get() + 1);
+                return Vec2::splat(f32::NAN);
+            }
+            vec2(4.0 * (1.5 - state.x), 0.0)
+        });
+
+        assert!(blow_ups.get() > 0, "no stage blew up");
+        // A single Dormand–Prince step takes 7 derivatives
+        assert!(calls.get() > 7, "the step wasn't split, {} derivatives were taken", calls.get());
+        assert!(state.is_finite(), "got {:?}", state);
+        assert!((state.x - (1.5 - 0.5 * (-4.0f32).exp())).abs() < 1.0e-4, "got {:?}", state);
+    }
+}
@@ -1,88 +1,60 @@
-use async_std::task::block_on;
-use clap::{Parser, Subcommand};
-use lazy_static::lazy_static;
-use wasm_bindgen::prelude::wasm_bindgen;
-use scenes::perlin_flow::{PerlinFlowOptions, PerlinFlowScene};
-use scenes::Scene;
-use crate::scenes::base3d::Base3DScene;
-use crate::scenes::lorenz::{LorenzOptions, LorenzScene};
+use wasm_bindgen::prelude::{JsValue, wasm_bindgen};
+use scenes::host::{HostOptions, HostScene};
+use scenes::parameters::ParameterValues;
+use scenes::registry::SceneRegistry;
 
-mod particle;
-mod scenes;
+pub mod benchmark;
+pub mod colormap;
+pub mod flow_grid;
+pub mod integrator;
+pub mod lifecycle;
+pub mod noise_field;
+pub mod palette;
+pub mod particle;
+pub mod plot;
+pub mod render;
+pub mod scenes;
+pub mod simulation;
+pub mod snapshot;
+pub mod timestep;
+pub mod world;
 #[path="3d/mod.rs"]
-mod math_3d;
+pub mod math_3d;
 
-lazy_static! {
-    static ref ARGS: Args = Args::parse();
-}
-
-
-#[derive(Parser)]
-pub struct Args {
-    #[command(subcommand)]
-    scene: SceneArgs,
-}
-
-#[derive(Subcommand)]
-enum SceneArgs {
-    /// Selects the Perlin Flow Model scene
-    PerlinFlow {
-        /// Toggles whether to show the direction vectors
-        #[arg(long, default_value_t = false)]
-        show_vectors: bool,
-
-        /// Toggles whether to show the moving dot
-        #[arg(long, default_value_t = false)]
-        hide_dots: bool
-    },
-    /// A 3D simulation of a Lorenz attractor
-    Lorenz {
-        /// The rho from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 28.0)]
-        rho: f32,
-        /// The sigma from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 10.0)]
-        sigma: f32,
-        /// The beta from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 2.66667)]
-        beta: f32,
-    },
-    Base3d {},
-}
 
+/// Starts the scene registered under `name`, with its options given as a JSON object.
+/// Options that are left out take their default values.
 #[wasm_bindgen]
-pub async fn main_base3d() {
+pub async fn start_scene(name: String, options_json: String) -> Result<(), JsValue> {
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
 
-    let app = Base3DScene::new_scene(&()).app().await;
-    app.run();
-}
+    let registry = SceneRegistry::default();
+    let entry = registry.get(&name)
+        .ok_or_else(|| JsValue::from_str(&format!("Unknown scene `{}`", name)))?;
+    let values = ParameterValues::from_json(&entry.parameters, &options_json)
+        .map_err(|e| JsValue::from_str(&e))?;
 
-#[wasm_bindgen]
-pub async fn main_perlin_flow(seed: u32) {
-    #[cfg(debug_assertions)]
-    console_error_panic_hook::set_once();
-
-    let app = PerlinFlowScene::new_scene(&PerlinFlowOptions {
-        show_vectors: false,
-        hide_dots: false,
-        seed: Some(seed),
-    }).app().await;
-    app.run();
+    entry.run(values, None, None).await
+        .map_err(|e| JsValue::from_str(&e))
 }
 
+/// Starts the host app on the scene registered under `name`.
+/// The other scenes can then be switched to with the number keys and Tab.
 #[wasm_bindgen]
-pub async fn main_lorenz() {
+pub async fn start_host(name: String, crossfade: f32) -> Result<(), JsValue> {
     #[cfg(debug_assertions)]
     console_error_panic_hook::set_once();
 
-    let app = LorenzScene::new_scene(&LorenzOptions {
-        rho: 28.0,
-        sigma: 10.0,
-        beta: 2.66667,
-    }).app().await;
+    let host = HostScene::new(HostOptions {
+        scene: name,
+        crossfade,
+    });
+    let app = host.app().await
+        .map_err(|e| JsValue::from_str(&e))?;
+
     app.run();
+    Ok(())
 }
 
 #[wasm_bindgen]
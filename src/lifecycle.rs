@@ -0,0 +1,147 @@
+//! How long particles live and what happens when they reach the edge of a scene.
+
+use rand::Rng;
+use serde_json::{json, Value};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// What happens to a particle that crosses the edge of a scene.
+/// Particles whose lifetime ends respawn at the emitter with `RespawnEmitter`, and anywhere within the edges otherwise.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoundaryPolicy {
+    /// The particle drifts on beyond the edge.
+    None,
+    /// The particle comes back in on the opposite side.
+    Wrap,
+    /// The particle is reflected back in, as if the edge were a wall.
+    Bounce,
+    /// The particle respawns at a random place within the edges.
+    RespawnRandom,
+    /// The particle respawns where the particles spawned at the start.
+    RespawnEmitter,
+}
+
+impl BoundaryPolicy {
+    pub const ALL: [BoundaryPolicy; 5] = [
+        BoundaryPolicy::None,
+        BoundaryPolicy::Wrap,
+        BoundaryPolicy::Bounce,
+        BoundaryPolicy::RespawnRandom,
+        BoundaryPolicy::RespawnEmitter,
+    ];
+
+    /// The names of all policies, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "none",
+        "wrap",
+        "bounce",
+        "respawn-random",
+        "respawn-emitter",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|policy| policy == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+
+    /// Applies the policy to a coordinate along a single axis, with the edges at `min` and `max`.
+    /// Returns `false` if the particle left and needs to respawn.
+    pub fn confine_axis(&self, position: &mut f32, velocity: &mut f32, min: f32, max: f32) -> bool {
+        if *position >= min && *position <= max {
+            return true;
+        }
+
+        match self {
+            BoundaryPolicy::None => true,
+            BoundaryPolicy::Wrap => {
+                *position = min + (*position - min).rem_euclid(max - min);
+                true
+            },
+            BoundaryPolicy::Bounce => {
+                // Reflected once, a particle that is further out than the width of the scene ends up on the other edge
+                *position = if *position < min { 2.0 * min - *position } else { 2.0 * max - *position };
+                *position = position.clamp(min, max);
+                *velocity = -*velocity;
+                true
+            },
+            BoundaryPolicy::RespawnRandom | BoundaryPolicy::RespawnEmitter => false,
+        }
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LifecycleOptions {
+    pub boundary: BoundaryPolicy,
+    /// The average lifetime of a particle in seconds, particles live forever at 0.
+    pub lifetime: f32,
+    /// How much the lifetimes vary, as a fraction of the average lifetime.
+    pub lifetime_variation: f32,
+    /// How many seconds particles take to fade in after spawning and to fade out before their lifetime ends.
+    pub fade_duration: f32,
+}
+
+impl Default for LifecycleOptions {
+    fn default() -> Self {
+        Self {
+            boundary: BoundaryPolicy::None,
+            lifetime: 0.0,
+            lifetime_variation: 0.5,
+            fade_duration: 0.0,
+        }
+    }
+}
+
+impl LifecycleOptions {
+    /// The parameters for the lifecycle, with the scene's defaults.
+    pub fn parameters(defaults: LifecycleOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::choice("boundary", "What happens to particles crossing the edge of the scene", BoundaryPolicy::NAMES, defaults.boundary.name()),
+            Parameter::float("lifetime", "The average lifetime of a particle in seconds, 0 lives forever", defaults.lifetime)
+                .with_range(0.0, 100_000.0),
+            Parameter::float("lifetime-variation", "How much the lifetimes vary, as a fraction of the average", defaults.lifetime_variation)
+                .with_range(0.0, 1.0),
+            Parameter::float("fade-duration", "How many seconds particles take to fade in and out", defaults.fade_duration)
+                .with_range(0.0, 100_000.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            boundary: BoundaryPolicy::from_name(values.choice("boundary")).unwrap(),
+            lifetime: values.float("lifetime"),
+            lifetime_variation: values.float("lifetime-variation"),
+            fade_duration: values.float("fade-duration"),
+        }
+    }
+
+    /// The options that change how the particles move by parameter name, for checking a snapshot against.
+    /// The fade only changes how they're drawn.
+    pub fn settings(&self) -> Value {
+        json!({
+            "boundary": self.boundary.name(),
+            "lifetime": self.lifetime,
+            "lifetime-variation": self.lifetime_variation,
+        })
+    }
+
+    /// Picks the lifetime of a new particle, `None` if particles live forever.
+    pub fn random_lifetime<R: Rng>(&self, rng: &mut R) -> Option<f32> {
+        if self.lifetime <= 0.0 {
+            return None;
+        }
+
+        let variation = self.lifetime * self.lifetime_variation;
+        if variation <= 0.0 {
+            return Some(self.lifetime);
+        }
+
+        Some(rng.gen_range(self.lifetime - variation..=self.lifetime + variation))
+    }
+}
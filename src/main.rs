@@ -1,84 +1,69 @@
-use rand::{Rng, thread_rng};
-use nannou::noise::{Perlin, Seedable};
-use nannou::noise;
-use nannou::noise::NoiseFn;
-use nannou::math::num_traits::Float;
-use nannou::prelude::*;
-
-use clap::{Parser, Subcommand};
-use lazy_static::lazy_static;
 use async_std::task::block_on;
-use nannou::app::Builder;
-use nannou::color::Alpha;
-use crate::particle::Particle2;
-use crate::scenes::base3d::Base3DScene;
-use crate::scenes::lorenz::{LorenzOptions, LorenzScene};
-use crate::scenes::perlin_flow::{PerlinFlowOptions, PerlinFlowScene};
-use crate::scenes::Scene;
-
-mod particle;
-mod scenes;
-#[path="3d/mod.rs"]
-mod math_3d;
+use clap::error::ErrorKind;
+use nannou_generative_art::{benchmark, plot, render};
+use nannou_generative_art::benchmark::BenchmarkOptions;
+use nannou_generative_art::plot::PlotOptions;
+use nannou_generative_art::render::RenderOptions;
+use nannou_generative_art::scenes::host::{HostOptions, HostScene};
+use nannou_generative_art::scenes::registry::SceneRegistry;
 
-lazy_static! {
-    static ref ARGS: Args = Args::parse();
-}
 
+fn main() {
+    // can't make a custom model yet: https://github.com/nannou-org/nannou/issues/793
+    // so the scenes hand their model over through a thread local instead
 
-#[derive(Parser)]
-pub struct Args {
-    #[command(subcommand)]
-    scene: SceneArgs,
-}
+    let registry = SceneRegistry::default();
+    let mut command = registry.command();
+    let matches = command.get_matches_mut();
 
-#[derive(Subcommand)]
-enum SceneArgs {
-    /// Selects the Perlin Flow Model scene
-    PerlinFlow {
-        /// Toggles whether to show the direction vectors
-        #[arg(long, default_value_t = false)]
-        show_vectors: bool,
+    match matches.subcommand() {
+        Some(("list", _)) => print!("{}", registry.describe()),
+        Some(("host", host_matches)) => {
+            let host = HostScene::new(HostOptions::from_matches(host_matches));
+            let app = block_on(host.app())
+                .unwrap_or_else(|e| command.error(ErrorKind::InvalidValue, e).exit());
 
-        /// Toggles whether to show the moving dot
-        #[arg(long, default_value_t = false)]
-        hide_dots: bool
-    },
-    /// A 3D simulation of a Lorenz attractor
-    Lorenz {
-        /// The rho from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 28.0)]
-        rho: f32,
-        /// The sigma from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 10.0)]
-        sigma: f32,
-        /// The beta from the Lorenz equation, see wikipedia
-        #[arg(long, default_value_t = 2.66667)]
-        beta: f32,
-    },
-    Base3d {},
-}
+            app.run();
+        }
+        Some(("render", render_matches)) => {
+            let options = RenderOptions::from_matches(render_matches);
+            let mut scene = registry.instance_from_matches(render_matches)
+                .unwrap_or_else(|e| command.error(ErrorKind::ValueValidation, e).exit());
 
-fn main() {
-    // can't make a custom model yet: https://github.com/nannou-org/nannou/issues/793
-    // incorporate this using lazy_static
+            render::render(scene.as_mut(), &options)
+                .unwrap_or_else(|e| command.error(ErrorKind::Io, e).exit());
+        }
+        Some(("export-svg", plot_matches)) => {
+            let options = PlotOptions::from_matches(plot_matches);
+            let mut scene = registry.plot_instance_from_matches(plot_matches)
+                .unwrap_or_else(|e| command.error(ErrorKind::ValueValidation, e).exit());
 
-    let args = Args::parse();
+            let stats = plot::export_svg(scene.as_mut(), &options)
+                .unwrap_or_else(|e| command.error(ErrorKind::Io, e).exit());
 
-    match args.scene {
-        SceneArgs::PerlinFlow { .. } => {
-            let app = block_on(PerlinFlowScene::new_scene(&PerlinFlowOptions::from_args(&args)).app());
-            app.run();
+            print!("{}", plot::describe_stats(&stats));
         }
-        SceneArgs::Lorenz { .. } => {
-            let app = block_on(LorenzScene::new_scene(&LorenzOptions::from_args(&args)).app());
-            app.run();
+        Some(("benchmark", benchmark_matches)) => {
+            let options = BenchmarkOptions::from_matches(benchmark_matches)
+                .unwrap_or_else(|e| command.error(ErrorKind::ValueValidation, e).exit());
+
+            let runs = benchmark::benchmark(&options)
+                .unwrap_or_else(|e| command.error(ErrorKind::InvalidValue, e).exit());
+
+            print!("{}", benchmark::describe_runs(&runs));
         }
-        SceneArgs::Base3d { .. } => {
-            let app = block_on(Base3DScene::new_scene(&()).app());
-            app.run();
+        Some((name, scene_matches)) => {
+            let entry = registry.get(name).unwrap();
+            let values = entry.values_from_matches(scene_matches)
+                .unwrap_or_else(|e| command.error(ErrorKind::ValueValidation, e).exit());
+            let config = entry.config_watcher(scene_matches)
+                .unwrap_or_else(|e| command.error(ErrorKind::ValueValidation, e).exit());
+
+            let snapshot = entry.snapshot_file(scene_matches);
+
+            block_on(entry.run(values, config, snapshot))
+                .unwrap_or_else(|e| command.error(ErrorKind::Io, e).exit());
         }
+        None => unreachable!("a subcommand is required"),
     }
-
-    // nannou::app(model).update(update).simple_window(view).size(1800, 1200).run();
 }
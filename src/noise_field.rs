@@ -0,0 +1,427 @@
+//! A choice of noise functions behind a single type, so a scene can pick its noise at runtime,
+//! and the sampling of that noise as a field that changes over time, either directly or through its curl.
+
+use std::f64::consts::TAU;
+
+use nannou::noise::{Billow, Fbm, HybridMulti, MultiFractal, NoiseFn, OpenSimplex, RidgedMulti, Seedable, Value, Worley};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// The step of the central differences the curl is computed with, in noise units.
+const CURL_EPSILON: f64 = 1.0e-4;
+
+/// How far apart the three potentials of the 3D curl are sampled, so they don't correlate.
+const POTENTIAL_OFFSETS: [[f64; 3]; 3] = [
+    [0.0, 0.0, 0.0],
+    [31.416, -47.853, 12.793],
+    [-73.190, 21.054, -55.309],
+];
+
+/// The directions the three potentials of the 3D curl drift in as the field evolves.
+/// Drifting apart they change the field without sampling 4D noise, whose Perlin variant jumps at the lattice in noise 0.7.
+const POTENTIAL_DRIFTS: [[f64; 3]; 3] = [
+    [0.0, 0.6, 0.8],
+    [0.8, 0.0, 0.6],
+    [0.6, 0.8, 0.0],
+];
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum NoiseKind {
+    Perlin,
+    OpenSimplex,
+    Value,
+    Worley,
+    Fbm,
+    Billow,
+    RidgedMulti,
+    HybridMulti,
+}
+
+impl NoiseKind {
+    pub const ALL: [NoiseKind; 8] = [
+        NoiseKind::Perlin,
+        NoiseKind::OpenSimplex,
+        NoiseKind::Value,
+        NoiseKind::Worley,
+        NoiseKind::Fbm,
+        NoiseKind::Billow,
+        NoiseKind::RidgedMulti,
+        NoiseKind::HybridMulti,
+    ];
+
+    /// The names of all kinds, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "perlin",
+        "open-simplex",
+        "value",
+        "worley",
+        "fbm",
+        "billow",
+        "ridged-multi",
+        "hybrid-multi",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|kind| kind == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct NoiseOptions {
+    pub kind: NoiseKind,
+    /// The amount of layers of detail the fractal kinds add up.
+    pub octaves: usize,
+    /// The frequency of the first octave of the fractal kinds, or of the cells of Worley noise.
+    pub frequency: f64,
+    /// The factor the frequency is multiplied by with each octave.
+    pub lacunarity: f64,
+    /// The factor the amplitude is multiplied by with each octave.
+    pub persistence: f64,
+}
+
+impl Default for NoiseOptions {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Perlin,
+            octaves: Fbm::DEFAULT_OCTAVE_COUNT,
+            frequency: Fbm::DEFAULT_FREQUENCY,
+            lacunarity: 2.0,
+            persistence: Fbm::DEFAULT_PERSISTENCE,
+        }
+    }
+}
+
+impl NoiseOptions {
+    /// The parameters for the noise, with the scene's defaults.
+    pub fn parameters(defaults: NoiseOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::choice("noise", "The noise function the field is built from", NoiseKind::NAMES, defaults.kind.name()),
+            Parameter::integer("octaves", "The amount of octaves of the fractal noise functions", defaults.octaves as i64)
+                .with_range(1.0, Fbm::MAX_OCTAVES as f64),
+            Parameter::float("frequency", "The frequency of the first octave, or of the Worley cells", defaults.frequency as f32)
+                .with_range(0.001, 1000.0),
+            Parameter::float("lacunarity", "The factor the frequency is multiplied by with each octave", defaults.lacunarity as f32)
+                .with_range(1.0, 10.0),
+            Parameter::float("persistence", "The factor the amplitude is multiplied by with each octave", defaults.persistence as f32)
+                .with_range(0.0, 1.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            kind: NoiseKind::from_name(values.choice("noise")).unwrap(),
+            octaves: values.integer("octaves") as usize,
+            frequency: values.float("frequency") as f64,
+            lacunarity: values.float("lacunarity") as f64,
+            persistence: values.float("persistence") as f64,
+        }
+    }
+
+    /// The options by parameter name, for checking a snapshot against.
+    pub fn settings(&self) -> serde_json::Value {
+        serde_json::json!({
+            "noise": self.kind.name(),
+            "octaves": self.octaves,
+            "frequency": self.frequency,
+            "lacunarity": self.lacunarity,
+            "persistence": self.persistence,
+        })
+    }
+}
+
+
+/// One of the noise functions of the `noise` crate, picked by `NoiseOptions`.
+/// The octave settings only apply to the fractal kinds, which are all built from Perlin noise.
+///
+/// noise 0.7 exports two generators named `Perlin`, the classic one and the surflet one, through the same glob,
+/// so any path to either is ambiguous. Perlin noise is held as a single octave of fBm at a frequency of 1,
+/// which gives exactly the classic generator the fractals are built from.
+#[derive(Clone, Debug)]
+pub enum NoiseField {
+    Perlin(Fbm),
+    OpenSimplex(OpenSimplex),
+    Value(Value),
+    Worley(Worley),
+    Fbm(Fbm),
+    Billow(Billow),
+    RidgedMulti(RidgedMulti),
+    HybridMulti(HybridMulti),
+}
+
+impl NoiseField {
+    /// Creates the noise function with a seed of 0, use `set_seed` to pick another.
+    pub fn new(options: &NoiseOptions) -> Self {
+        match options.kind {
+            NoiseKind::Perlin => NoiseField::Perlin(Fbm::new().set_octaves(1).set_frequency(1.0)),
+            NoiseKind::OpenSimplex => NoiseField::OpenSimplex(OpenSimplex::new()),
+            NoiseKind::Value => NoiseField::Value(Value::new()),
+            NoiseKind::Worley => NoiseField::Worley(Worley::new().set_frequency(options.frequency)),
+            NoiseKind::Fbm => NoiseField::Fbm(fractal(Fbm::new(), options)),
+            NoiseKind::Billow => NoiseField::Billow(fractal(Billow::new(), options)),
+            NoiseKind::RidgedMulti => NoiseField::RidgedMulti(fractal(RidgedMulti::new(), options)),
+            NoiseKind::HybridMulti => NoiseField::HybridMulti(fractal(HybridMulti::new(), options)),
+        }
+    }
+}
+
+fn fractal<T: MultiFractal>(noise_fn: T, options: &NoiseOptions) -> T {
+    noise_fn
+        .set_octaves(options.octaves)
+        .set_frequency(options.frequency)
+        .set_lacunarity(options.lacunarity)
+        .set_persistence(options.persistence)
+}
+
+impl Seedable for NoiseField {
+    fn set_seed(self, seed: u32) -> Self {
+        match self {
+            NoiseField::Perlin(noise_fn) => NoiseField::Perlin(noise_fn.set_seed(seed)),
+            NoiseField::OpenSimplex(noise_fn) => NoiseField::OpenSimplex(noise_fn.set_seed(seed)),
+            NoiseField::Value(noise_fn) => NoiseField::Value(noise_fn.set_seed(seed)),
+            NoiseField::Worley(noise_fn) => NoiseField::Worley(noise_fn.set_seed(seed)),
+            NoiseField::Fbm(noise_fn) => NoiseField::Fbm(noise_fn.set_seed(seed)),
+            NoiseField::Billow(noise_fn) => NoiseField::Billow(noise_fn.set_seed(seed)),
+            NoiseField::RidgedMulti(noise_fn) => NoiseField::RidgedMulti(noise_fn.set_seed(seed)),
+            NoiseField::HybridMulti(noise_fn) => NoiseField::HybridMulti(noise_fn.set_seed(seed)),
+        }
+    }
+
+    fn seed(&self) -> u32 {
+        match self {
+            NoiseField::Perlin(noise_fn) => noise_fn.seed(),
+            NoiseField::OpenSimplex(noise_fn) => noise_fn.seed(),
+            NoiseField::Value(noise_fn) => noise_fn.seed(),
+            NoiseField::Worley(noise_fn) => noise_fn.seed(),
+            NoiseField::Fbm(noise_fn) => noise_fn.seed(),
+            NoiseField::Billow(noise_fn) => noise_fn.seed(),
+            NoiseField::RidgedMulti(noise_fn) => noise_fn.seed(),
+            NoiseField::HybridMulti(noise_fn) => noise_fn.seed(),
+        }
+    }
+}
+
+/// Forwards sampling in 2 and 3 dimensions to the wrapped noise function.
+macro_rules! impl_noise_fn {
+    ($($dimensions:literal),*) => {
+        $(
+            impl NoiseFn<[f64; $dimensions]> for NoiseField {
+                fn get(&self, point: [f64; $dimensions]) -> f64 {
+                    match self {
+                        NoiseField::Perlin(noise_fn) => noise_fn.get(point),
+                        NoiseField::OpenSimplex(noise_fn) => noise_fn.get(point),
+                        NoiseField::Value(noise_fn) => noise_fn.get(point),
+                        NoiseField::Worley(noise_fn) => noise_fn.get(point),
+                        NoiseField::Fbm(noise_fn) => noise_fn.get(point),
+                        NoiseField::Billow(noise_fn) => noise_fn.get(point),
+                        NoiseField::RidgedMulti(noise_fn) => noise_fn.get(point),
+                        NoiseField::HybridMulti(noise_fn) => noise_fn.get(point),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_noise_fn!(2, 3);
+
+
+/// Noise that can be sampled in 2 and 3 dimensions, the third dimension being used for time.
+pub trait FieldNoise: NoiseFn<[f64; 2]> + NoiseFn<[f64; 3]> {}
+
+impl<T> FieldNoise for T
+    where
+        T: NoiseFn<[f64; 2]> + NoiseFn<[f64; 3]>
+{}
+
+
+/// How a noise field changes over time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EvolutionOptions {
+    /// How far the field moves through time per simulated second, in noise units. The field is static at 0.
+    pub speed: f64,
+    /// After how many simulated seconds the field returns to how it started, it never does at 0.
+    pub loop_duration: f64,
+}
+
+impl Default for EvolutionOptions {
+    fn default() -> Self {
+        Self {
+            speed: 0.0,
+            loop_duration: 0.0,
+        }
+    }
+}
+
+impl EvolutionOptions {
+    /// The parameters for the evolution, with the scene's defaults.
+    pub fn parameters(defaults: EvolutionOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::float("evolution-speed", "How fast the field changes over time, 0 keeps it static", defaults.speed as f32)
+                .with_range(0.0, 100.0),
+            Parameter::float("loop-duration", "After how many seconds the changing field repeats itself, 0 never repeats", defaults.loop_duration as f32)
+                .with_range(0.0, 100_000.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            speed: values.float("evolution-speed") as f64,
+            loop_duration: values.float("loop-duration") as f64,
+        }
+    }
+
+    /// The options by parameter name, for checking a snapshot against.
+    pub fn settings(&self) -> serde_json::Value {
+        serde_json::json!({
+            "evolution-speed": self.speed,
+            "loop-duration": self.loop_duration,
+        })
+    }
+
+    /// Samples the noise at the point as it is `time` seconds into the simulation.
+    /// A static field samples 2D noise and an evolving one uses time as the third dimension, starting at 0.
+    /// That slice of 3D noise isn't the 2D noise, so turning the evolution on in a running scene swaps the field
+    /// for another one at once, rather than setting the static one in motion.
+    /// A looping field moves around a circle through the third dimension and the first,
+    /// so it drifts sideways as it changes and returns to the same noise after a loop without reversing.
+    /// Looping through 4D noise would keep it in place, but the Perlin variants jump at the lattice in noise 0.7.
+    pub fn sample<T: FieldNoise>(&self, noise_fn: &T, point: [f64; 2], time: f64) -> f64 {
+        let [x, y] = point;
+
+        if self.speed == 0.0 {
+            NoiseFn::<[f64; 2]>::get(noise_fn, point)
+        } else if self.loop_duration > 0.0 {
+            // The circumference is the distance travelled through time in a loop, so the speed stays the same
+            let radius = self.speed * self.loop_duration / TAU;
+            let angle = TAU * (time / self.loop_duration).fract();
+            // Starting on the slice at 0, like the field that doesn't loop
+            NoiseFn::<[f64; 3]>::get(noise_fn, [x + radius * (angle.cos() - 1.0), y, radius * angle.sin()])
+        } else {
+            NoiseFn::<[f64; 3]>::get(noise_fn, [x, y, time * self.speed])
+        }
+    }
+
+    /// The curl of the noise at the point as it is `time` seconds into the simulation,
+    /// which is the gradient turned a quarter clockwise, in noise units.
+    pub fn curl<T: FieldNoise>(&self, noise_fn: &T, point: [f64; 2], time: f64) -> [f64; 2] {
+        let [x, y] = point;
+        let potential = |x: f64, y: f64| self.sample(noise_fn, [x, y], time);
+
+        let dx = (potential(x + CURL_EPSILON, y) - potential(x - CURL_EPSILON, y)) / (2.0 * CURL_EPSILON);
+        let dy = (potential(x, y + CURL_EPSILON) - potential(x, y - CURL_EPSILON)) / (2.0 * CURL_EPSILON);
+
+        [dy, -dx]
+    }
+}
+
+
+/// How a flow field turns noise into directions.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FieldMode {
+    /// The noise is the angle of the flow, which has sinks the particles clump in.
+    Angle,
+    /// The flow is the curl of the noise, which is free of divergence, so the particles stay evenly spread.
+    Curl,
+}
+
+impl FieldMode {
+    pub const ALL: [FieldMode; 2] = [FieldMode::Angle, FieldMode::Curl];
+
+    /// The names of all modes, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &["angle", "curl"];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+/// The curl of a 3D vector potential built from the noise, at the point with the field evolved by `w`.
+/// The three components of the potential sample the noise at offset points, which drift apart as `w` grows.
+pub fn curl_3d<T: FieldNoise>(noise_fn: &T, point: [f64; 3], w: f64) -> [f64; 3] {
+    let potential = |component: usize, offset: [f64; 3]| {
+        let [dx, dy, dz] = POTENTIAL_OFFSETS[component];
+        let [drift_x, drift_y, drift_z] = POTENTIAL_DRIFTS[component];
+        NoiseFn::<[f64; 3]>::get(noise_fn, [
+            point[0] + offset[0] + dx + w * drift_x,
+            point[1] + offset[1] + dy + w * drift_y,
+            point[2] + offset[2] + dz + w * drift_z,
+        ])
+    };
+
+    // The derivative of a component of the potential along an axis
+    let derivative = |component: usize, axis: usize| {
+        let mut offset = [0.0; 3];
+        offset[axis] = CURL_EPSILON;
+        let after = potential(component, offset);
+        offset[axis] = -CURL_EPSILON;
+        let before = potential(component, offset);
+
+        (after - before) / (2.0 * CURL_EPSILON)
+    };
+
+    [
+        derivative(2, 1) - derivative(1, 2),
+        derivative(0, 2) - derivative(2, 0),
+        derivative(1, 0) - derivative(0, 1),
+    ]
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn looping() -> EvolutionOptions {
+        EvolutionOptions {
+            speed: 0.5,
+            loop_duration: 10.0,
+        }
+    }
+
+    #[test]
+    fn looping_fields_return_to_where_they_started() {
+        for kind in NoiseKind::ALL {
+            let noise_fn = NoiseField::new(&NoiseOptions { kind, ..NoiseOptions::default() });
+
+            for point in [[0.3, 0.7], [2.1, -1.4]] {
+                let start = looping().sample(&noise_fn, point, 0.0);
+                let after_loop = looping().sample(&noise_fn, point, 10.0);
+
+                assert!((start - after_loop).abs() < 1.0e-9, "{} doesn't loop at {:?}", kind.name(), point);
+            }
+        }
+    }
+
+    #[test]
+    fn looping_perlin_fields_change_continuously() {
+        let noise_fn = NoiseField::new(&NoiseOptions::default());
+        let point = [0.3, 0.7];
+        let dt = 1.0e-3;
+
+        // Small steps through the whole loop, which crosses many lattice cells
+        let largest_change = (0..10_000)
+            .map(|step| {
+                let time = step as f64 * dt;
+                (looping().sample(&noise_fn, point, time + dt) - looping().sample(&noise_fn, point, time)).abs()
+            })
+            .fold(0.0, f64::max);
+
+        assert!(largest_change < 0.01, "the field jumps by {}", largest_change);
+    }
+}
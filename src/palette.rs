@@ -0,0 +1,349 @@
+//! Palettes the particle colors and the background are picked from.
+//! A palette is a gradient through a few colors or an Inigo Quilez style cosine palette,
+//! either built in, written inline on the command line or read from a file of hex colors.
+
+use std::fs;
+use std::path::Path;
+
+use nannou::color::LinSrgb;
+use nannou::prelude::*;
+use crate::scenes::parameters::parse_hex_color;
+
+
+/// The most colors a gradient holds, which keeps palettes `Copy` like the rest of the scene options.
+pub const MAX_STOPS: usize = 16;
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub colors: PaletteColors,
+    /// The color the scene is drawn onto.
+    pub background: Srgb<u8>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaletteColors {
+    Gradient(Gradient),
+    Cosine(CosinePalette),
+}
+
+/// Evenly spaced colors, blended between neighbours.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gradient {
+    stops: [Srgb<u8>; MAX_STOPS],
+    len: usize,
+}
+
+/// The palette `a + b * cos(TAU * (c * t + d))` from https://iquilezles.org/articles/palettes/,
+/// with each parameter giving the red, green and blue components.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CosinePalette {
+    pub a: Vec3,
+    pub b: Vec3,
+    pub c: Vec3,
+    pub d: Vec3,
+}
+
+/// The space gradients blend their colors in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// Linear light, which keeps the brightness even but can dull the hues in between.
+    Linear,
+    /// The OKLab space, which blends along perceptually even steps.
+    Oklab,
+}
+
+impl Interpolation {
+    pub const ALL: [Interpolation; 2] = [
+        Interpolation::Linear,
+        Interpolation::Oklab,
+    ];
+
+    /// The names of all interpolations, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "linear",
+        "oklab",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|interpolation| interpolation == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+
+impl Palette {
+    /// The names of the built-in palettes.
+    pub const NAMES: &'static [&'static str] = &[
+        "dusk",
+        "parchment",
+        "viridis",
+        "inferno",
+        "sunset",
+        "ocean",
+        "grayscale",
+        "rainbow",
+        "candy",
+        "copper",
+        "peach",
+    ];
+
+    /// The built-in palette with the name.
+    pub fn named(name: &str) -> Option<Palette> {
+        let palette = match name {
+            "dusk" => Self::built_in_gradient(&["#1e90ff", "#ff4500"], "#000000"),
+            "parchment" => Self::built_in_gradient(&["#191970", "#1e90ff", "#ff4500"], "#ffebcd"),
+            "viridis" => Self::built_in_gradient(&["#440154", "#414487", "#2a788e", "#22a884", "#7ad151", "#fde725"], "#000000"),
+            "inferno" => Self::built_in_gradient(&["#420a68", "#932667", "#dd513a", "#fca50a", "#fcffa4"], "#000004"),
+            "sunset" => Self::built_in_gradient(&["#355c7d", "#6c5b7b", "#c06c84", "#f67280", "#f8b195"], "#1a1a2e"),
+            "ocean" => Self::built_in_gradient(&["#03045e", "#0077b6", "#00b4d8", "#90e0ef", "#caf0f8"], "#000814"),
+            "grayscale" => Self::built_in_gradient(&["#000000", "#ffffff"], "#808080"),
+            "rainbow" => Self::built_in_cosine([0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [1.0, 1.0, 1.0], [0.0, 0.33, 0.67], "#000000"),
+            "candy" => Self::built_in_cosine([0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [1.0, 1.0, 0.5], [0.8, 0.9, 0.3], "#000000"),
+            "copper" => Self::built_in_cosine([0.5, 0.5, 0.5], [0.5, 0.5, 0.5], [1.0, 0.7, 0.4], [0.0, 0.15, 0.2], "#000000"),
+            "peach" => Self::built_in_cosine([0.8, 0.5, 0.4], [0.2, 0.4, 0.2], [2.0, 1.0, 1.0], [0.0, 0.25, 0.25], "#1a1a1a"),
+            _ => return None,
+        };
+
+        Some(palette)
+    }
+
+    /// The name of the palette if it is a built-in one.
+    pub fn name(&self) -> Option<&'static str> {
+        Self::NAMES.iter()
+            .find(|name| Self::named(name).as_ref() == Some(self))
+            .copied()
+    }
+
+    fn built_in_gradient(stops: &[&str], background: &str) -> Palette {
+        let stops = stops.iter().map(|hex| parse_hex_color(hex).unwrap()).collect::<Vec<_>>();
+
+        Palette {
+            colors: PaletteColors::Gradient(Gradient::new(&stops).unwrap()),
+            background: parse_hex_color(background).unwrap(),
+        }
+    }
+
+    fn built_in_cosine(a: [f32; 3], b: [f32; 3], c: [f32; 3], d: [f32; 3], background: &str) -> Palette {
+        Palette {
+            colors: PaletteColors::Cosine(CosinePalette {
+                a: Vec3::from(a),
+                b: Vec3::from(b),
+                c: Vec3::from(c),
+                d: Vec3::from(d),
+            }),
+            background: parse_hex_color(background).unwrap(),
+        }
+    }
+
+    /// Reads a palette from the command line or a config file. It is either
+    /// - the name of a built-in palette, like `viridis`,
+    /// - a cosine palette like `cosine:0.5,0.5,0.5;0.5,0.5,0.5;1,1,1;0,0.33,0.67`, giving `a`, `b`, `c` and `d`,
+    /// - the path of a file of hex colors, see `from_hex_list`,
+    /// - or hex colors separated by commas, like `#003049,#d62828,#fcbf49`.
+    ///
+    /// Palettes that don't name their background are drawn onto black.
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        if let Some(palette) = Self::named(spec) {
+            return Ok(palette);
+        }
+
+        if let Some(parameters) = spec.strip_prefix("cosine:") {
+            return CosinePalette::parse(parameters)
+                .map(|cosine| Palette {
+                    colors: PaletteColors::Cosine(cosine),
+                    background: BLACK,
+                });
+        }
+
+        let path = Path::new(spec);
+        if path.is_file() {
+            return Self::load(path);
+        }
+
+        Self::from_hex_list(spec).map_err(|e| format!(
+            "\"{}\" is neither a palette ({}), a cosine palette, a list of hex colors nor a palette file: {}",
+            spec,
+            Self::NAMES.join(", "),
+            e,
+        ))
+    }
+
+    /// Reads a palette from a file of hex colors, see `from_hex_list`.
+    pub fn load(path: &Path) -> Result<Palette, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read palette file {}: {}", path.display(), e))?;
+
+        Self::from_hex_list(&text)
+            .map_err(|e| format!("Invalid palette file {}: {}", path.display(), e))
+    }
+
+    /// Builds a gradient from hex colors separated by whitespace or commas.
+    /// Lines starting with `//` are comments, and a color following the word `background` sets the background.
+    pub fn from_hex_list(text: &str) -> Result<Palette, String> {
+        let mut stops = Vec::new();
+        let mut background = BLACK;
+
+        for line in text.lines().map(str::trim).filter(|line| !line.starts_with("//")) {
+            let mut words = line.split(|c: char| c.is_whitespace() || c == ',').filter(|word| !word.is_empty());
+
+            while let Some(word) = words.next() {
+                if word == "background" {
+                    let hex = words.next().ok_or("`background` isn't followed by a color")?;
+                    background = parse_hex_color(hex).ok_or_else(|| format!("\"{}\" is not a hex color", hex))?;
+                } else {
+                    stops.push(parse_hex_color(word).ok_or_else(|| format!("\"{}\" is not a hex color", word))?);
+                }
+            }
+        }
+
+        Ok(Palette {
+            colors: PaletteColors::Gradient(Gradient::new(&stops)?),
+            background,
+        })
+    }
+
+    /// The color at `t` in `[0, 1]` along the palette.
+    pub fn color(&self, t: f32, interpolation: Interpolation) -> Rgb {
+        match &self.colors {
+            PaletteColors::Gradient(gradient) => gradient.color(t, interpolation),
+            PaletteColors::Cosine(cosine) => cosine.color(t),
+        }
+    }
+}
+
+
+impl Gradient {
+    /// A gradient through the colors, of which there are between 1 and `MAX_STOPS`.
+    pub fn new(colors: &[Srgb<u8>]) -> Result<Gradient, String> {
+        if colors.is_empty() || colors.len() > MAX_STOPS {
+            return Err(format!("A gradient takes between 1 and {} colors, got {}", MAX_STOPS, colors.len()));
+        }
+
+        let mut stops = [BLACK; MAX_STOPS];
+        stops[..colors.len()].copy_from_slice(colors);
+
+        Ok(Gradient {
+            stops,
+            len: colors.len(),
+        })
+    }
+
+    pub fn stops(&self) -> &[Srgb<u8>] {
+        &self.stops[..self.len]
+    }
+
+    pub fn color(&self, t: f32, interpolation: Interpolation) -> Rgb {
+        let stops = self.stops();
+        if stops.len() == 1 {
+            return stops[0].into_format();
+        }
+
+        let position = t.clamp(0.0, 1.0) * (stops.len() - 1) as f32;
+        let index = (position.floor() as usize).min(stops.len() - 2);
+        let start = linear(stops[index]);
+        let end = linear(stops[index + 1]);
+        let factor = position - index as f32;
+
+        let blended = match interpolation {
+            Interpolation::Linear => start.lerp(end, factor),
+            Interpolation::Oklab => oklab_to_linear(linear_to_oklab(start).lerp(linear_to_oklab(end), factor)),
+        };
+
+        from_linear(blended)
+    }
+}
+
+
+impl CosinePalette {
+    /// Parses the four parameters separated by semicolons, each with its three components separated by commas.
+    pub fn parse(text: &str) -> Result<CosinePalette, String> {
+        let parameters = text.split(';')
+            .map(|parameter| {
+                let components = parameter.split(',')
+                    .map(|component| component.trim().parse::<f32>()
+                        .map_err(|_| format!("\"{}\" is not a number", component.trim())))
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                match components[..] {
+                    [r, g, b] => Ok(vec3(r, g, b)),
+                    _ => Err(format!("\"{}\" should be three numbers separated by commas", parameter)),
+                }
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        match parameters[..] {
+            [a, b, c, d] => Ok(CosinePalette { a, b, c, d }),
+            _ => Err(format!("A cosine palette takes four parameters separated by semicolons, got {}", parameters.len())),
+        }
+    }
+
+    pub fn color(&self, t: f32) -> Rgb {
+        let phase = (self.c * t + self.d) * TAU;
+        let color = self.a + self.b * vec3(phase.x.cos(), phase.y.cos(), phase.z.cos());
+        let color = color.clamp(Vec3::ZERO, Vec3::ONE);
+
+        Rgb::new(color.x, color.y, color.z)
+    }
+}
+
+
+/// Blends from one color to the other in linear light, like the gradients do.
+pub fn mix(start: Rgb, end: Rgb, factor: f32) -> Rgb {
+    let [start, end] = [start, end].map(|color| {
+        let color: LinSrgb = color.into_linear();
+        vec3(color.red, color.green, color.blue)
+    });
+
+    from_linear(start.lerp(end, factor))
+}
+
+fn linear(color: Srgb<u8>) -> Vec3 {
+    let color: LinSrgb = color.into_format::<f32>().into_linear();
+    vec3(color.red, color.green, color.blue)
+}
+
+fn from_linear(color: Vec3) -> Rgb {
+    let color = color.clamp(Vec3::ZERO, Vec3::ONE);
+    Rgb::from_linear(LinSrgb::new(color.x, color.y, color.z))
+}
+
+/// Converts linear sRGB to OKLab, see https://bottosson.github.io/posts/oklab/.
+// The matrices are kept as published, though f32 doesn't hold all their digits
+#[allow(clippy::excessive_precision)]
+fn linear_to_oklab(color: Vec3) -> Vec3 {
+    let lms = vec3(
+        vec3(0.4122214708, 0.5363325363, 0.0514459929).dot(color),
+        vec3(0.2119034982, 0.6806995451, 0.1073969566).dot(color),
+        vec3(0.0883024619, 0.2817188376, 0.6299787005).dot(color),
+    );
+    let lms = vec3(lms.x.cbrt(), lms.y.cbrt(), lms.z.cbrt());
+
+    vec3(
+        vec3(0.2104542553, 0.7936177850, -0.0040720468).dot(lms),
+        vec3(1.9779984951, -2.4285922050, 0.4505937099).dot(lms),
+        vec3(0.0259040371, 0.7827717662, -0.8086757660).dot(lms),
+    )
+}
+
+#[allow(clippy::excessive_precision)]
+fn oklab_to_linear(color: Vec3) -> Vec3 {
+    let lms = vec3(
+        vec3(1.0, 0.3963377774, 0.2158037573).dot(color),
+        vec3(1.0, -0.1055613458, -0.0638541728).dot(color),
+        vec3(1.0, -0.0894841775, -1.2914855480).dot(color),
+    );
+    let lms = lms * lms * lms;
+
+    vec3(
+        vec3(4.0767416621, -3.3077115913, 0.2309699292).dot(lms),
+        vec3(-1.2684380046, 2.6097574011, -0.3413193965).dot(lms),
+        vec3(-0.0041960863, -0.7034186147, 1.7076147010).dot(lms),
+    )
+}
@@ -1,15 +1,29 @@
+use std::collections::VecDeque;
+
 use nannou::color::Alpha;
 use nannou::prelude::*;
+use serde_json::{json, Value};
+use crate::integrator::{IntegratorKind, IntegratorOptions};
+use crate::lifecycle::BoundaryPolicy;
+use crate::snapshot::{color_field, color_json, f32_field, field, paths_field, Snapshot, vec2_field, vec2s_field, vec3_field, vec3s_field};
 
 
 pub const MAX_VELOCITY: f32 = 40.0;
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Particle2 {
     pub position: Point2,
     pub velocity: Vec2,
     pub radius: f32,
     pub color: Alpha<Rgb, f32>,
+    /// The seconds since the particle (re)spawned.
+    pub age: f32,
+    /// The age at which the particle respawns, it lives forever when `None`.
+    pub lifetime: Option<f32>,
+    /// The positions recorded with `record_position`, empty unless the scene records trails.
+    pub path: Vec<Point2>,
+    /// The recorded paths that ended when the particle jumped, by wrapping around or respawning.
+    pub finished_paths: Vec<Vec<Point2>>,
 }
 
 impl Particle2 {
@@ -19,9 +33,64 @@ impl Particle2 {
             velocity: vec2(0.0, 0.0),
             radius: 1.0,
             color,
+            age: 0.0,
+            lifetime: None,
+            path: Vec::new(),
+            finished_paths: Vec::new(),
+        }
+    }
+
+    /// Adds the current position to the path,
+    /// unless it lies closer than `min_distance` to the last recorded one.
+    pub fn record_position(&mut self, min_distance: f32) {
+        match self.path.last() {
+            Some(last) if last.distance(self.position) < min_distance => {},
+            _ => self.path.push(self.position),
         }
     }
 
+    /// Ends the recorded path, so the jump to the next position isn't drawn.
+    /// Recording continues with a new path.
+    pub fn break_path(&mut self) {
+        let path = std::mem::take(&mut self.path);
+        if path.len() > 1 {
+            self.finished_paths.push(path);
+        }
+    }
+
+    /// Starts a new life at the position.
+    pub fn respawn(&mut self, position: Point2, lifetime: Option<f32>) {
+        self.break_path();
+        self.position = position;
+        self.velocity = vec2(0.0, 0.0);
+        self.age = 0.0;
+        self.lifetime = lifetime;
+    }
+
+    /// Applies the boundary policy to keep the particle within the bounds.
+    /// Returns `false` if it left and needs to respawn.
+    pub fn confine(&mut self, bounds: Rect, policy: BoundaryPolicy) -> bool {
+        let position = self.position;
+        let inside = policy.confine_axis(&mut self.position.x, &mut self.velocity.x, bounds.left(), bounds.right())
+            & policy.confine_axis(&mut self.position.y, &mut self.velocity.y, bounds.bottom(), bounds.top());
+
+        if policy == BoundaryPolicy::Wrap && self.position != position {
+            self.break_path();
+        }
+
+        inside
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.lifetime.is_some_and(|lifetime| self.age >= lifetime)
+    }
+
+    /// The factor the alpha is multiplied by, fading in over `fade_duration` seconds after spawning
+    /// and fading out over the same time before the lifetime ends.
+    pub fn fade(&self, fade_duration: f32) -> f32 {
+        fade(self.age, self.lifetime, fade_duration)
+    }
+
 
     /// Updates its position and velocity given a force vector, and the amount of time passed.
     pub fn process_force(&mut self, force: Vec2, time_passed: f32) {
@@ -38,18 +107,44 @@ impl Particle2 {
         self.move_at_velocity(time_passed);
     }
 
-    pub fn position_scaled(&self, app: &App) -> Point2 {
-        scale_coords(app, self.position)
+    /// The position and velocity after `time_passed`, pushed by the force the field gives at every position.
+    /// Euler keeps applying the force before moving like `update`, so scenes look the same by default.
+    pub fn integrate<F>(&self, integrator: &IntegratorOptions, force_at: F, time_passed: f32) -> (Point2, Vec2)
+        where
+            F: Fn(Point2) -> Vec2
+    {
+        if integrator.kind == IntegratorKind::Euler {
+            let velocity = (self.velocity + force_at(self.position) * time_passed).clamp_length_max(MAX_VELOCITY);
+            return (self.position + velocity * time_passed, velocity);
+        }
+
+        let (position, velocity) = integrator.step(
+            (self.position, self.velocity),
+            time_passed,
+            |(position, velocity): (Point2, Vec2)| (velocity.clamp_length_max(MAX_VELOCITY), force_at(position)),
+        );
+        (position, velocity.clamp_length_max(MAX_VELOCITY))
+    }
+
+    /// Returns the position scaled to `[0, 1]` relative to the given bounds.
+    pub fn position_scaled(&self, bounds: Rect) -> Point2 {
+        scale_coords(bounds, self.position)
     }
 }
 
 
-#[derive(Copy, Clone, Debug, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Particle3 {
     pub position: Point3,
     pub velocity: Vec3,
     pub radius: f32,
     pub color: Alpha<Rgb, f32>,
+    /// The seconds since the particle (re)spawned.
+    pub age: f32,
+    /// The age at which the particle respawns, it lives forever when `None`.
+    pub lifetime: Option<f32>,
+    /// The latest positions recorded with `record_trail`, oldest first, empty unless the scene draws trails.
+    pub trail: VecDeque<Point3>,
 }
 
 impl Particle3 {
@@ -59,7 +154,53 @@ impl Particle3 {
             velocity: vec3(0.0, 0.0, 0.0),
             radius,
             color,
+            age: 0.0,
+            lifetime: None,
+            trail: VecDeque::new(),
+        }
+    }
+
+    /// Adds the current position to the trail, dropping the oldest ones to keep at most `length` of them.
+    pub fn record_trail(&mut self, length: usize) {
+        self.trail.push_back(self.position);
+        while self.trail.len() > length {
+            self.trail.pop_front();
+        }
+    }
+
+    /// Starts a new life at the position.
+    pub fn respawn(&mut self, position: Point3, lifetime: Option<f32>) {
+        self.trail.clear();
+        self.position = position;
+        self.velocity = vec3(0.0, 0.0, 0.0);
+        self.age = 0.0;
+        self.lifetime = lifetime;
+    }
+
+    /// Applies the boundary policy to keep the particle within the box from `min` to `max`.
+    /// Returns `false` if it left and needs to respawn.
+    pub fn confine(&mut self, min: Point3, max: Point3, policy: BoundaryPolicy) -> bool {
+        let position = self.position;
+        let inside = policy.confine_axis(&mut self.position.x, &mut self.velocity.x, min.x, max.x)
+            & policy.confine_axis(&mut self.position.y, &mut self.velocity.y, min.y, max.y)
+            & policy.confine_axis(&mut self.position.z, &mut self.velocity.z, min.z, max.z);
+
+        // The trail would otherwise be drawn across the box
+        if policy == BoundaryPolicy::Wrap && self.position != position {
+            self.trail.clear();
         }
+
+        inside
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.lifetime.is_some_and(|lifetime| self.age >= lifetime)
+    }
+
+    /// The factor the alpha is multiplied by, fading in over `fade_duration` seconds after spawning
+    /// and fading out over the same time before the lifetime ends.
+    pub fn fade(&self, fade_duration: f32) -> f32 {
+        fade(self.age, self.lifetime, fade_duration)
     }
 
 
@@ -85,8 +226,101 @@ impl Particle3 {
 }
 
 
-pub fn scale_coords(app: &App, coords: Point2) -> Point2 {
-    let win = app.window_rect();
+fn fade(age: f32, lifetime: Option<f32>, fade_duration: f32) -> f32 {
+    if fade_duration <= 0.0 {
+        return 1.0;
+    }
+
+    let fade_in = age / fade_duration;
+    let fade_out = lifetime.map_or(1.0, |lifetime| (lifetime - age) / fade_duration);
+    fade_in.min(fade_out).clamp(0.0, 1.0)
+}
+
+/// Scales the coordinates to `[0, 1]` relative to the given bounds, e.g. the window rect.
+pub fn scale_coords(bounds: Rect, coords: Point2) -> Point2 {
+    (coords - bounds.bottom_left()) / bounds.wh()
+}
+
+
+impl Snapshot for Particle2 {
+    fn snapshot(&self) -> Value {
+        json!({
+            "position": self.position.to_array(),
+            "velocity": self.velocity.to_array(),
+            "radius": self.radius,
+            "color": color_json(self.color),
+            "age": self.age,
+            "lifetime": self.lifetime,
+            "path": self.path.iter().map(|point| point.to_array()).collect::<Vec<_>>(),
+            "finished_paths": self.finished_paths.iter()
+                .map(|path| path.iter().map(|point| point.to_array()).collect::<Vec<_>>())
+                .collect::<Vec<_>>(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let position = vec2_field(snapshot, "position")?;
+        let velocity = vec2_field(snapshot, "velocity")?;
+        let radius = f32_field(snapshot, "radius")?;
+        let color = color_field(snapshot, "color")?;
+        let age = f32_field(snapshot, "age")?;
+        let lifetime = lifetime_field(snapshot)?;
+        let path = vec2s_field(snapshot, "path")?;
+        let finished_paths = paths_field(snapshot, "finished_paths")?;
+
+        self.position = position;
+        self.velocity = velocity;
+        self.radius = radius;
+        self.color = color;
+        self.age = age;
+        self.lifetime = lifetime;
+        self.path = path;
+        self.finished_paths = finished_paths;
+
+        Ok(())
+    }
+}
+
+impl Snapshot for Particle3 {
+    fn snapshot(&self) -> Value {
+        json!({
+            "position": self.position.to_array(),
+            "velocity": self.velocity.to_array(),
+            "radius": self.radius,
+            "color": color_json(self.color),
+            "age": self.age,
+            "lifetime": self.lifetime,
+            "trail": self.trail.iter().map(|point| point.to_array()).collect::<Vec<_>>(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let position = vec3_field(snapshot, "position")?;
+        let velocity = vec3_field(snapshot, "velocity")?;
+        let radius = f32_field(snapshot, "radius")?;
+        let color = color_field(snapshot, "color")?;
+        let age = f32_field(snapshot, "age")?;
+        let lifetime = lifetime_field(snapshot)?;
+        let trail = vec3s_field(snapshot, "trail")?;
+
+        self.position = position;
+        self.velocity = velocity;
+        self.radius = radius;
+        self.color = color;
+        self.age = age;
+        self.lifetime = lifetime;
+        self.trail = trail.into();
+
+        Ok(())
+    }
+}
+
+/// A lifetime written as a number, or as `null` for particles living forever.
+fn lifetime_field(snapshot: &Value) -> Result<Option<f32>, String> {
+    let lifetime = field(snapshot, "lifetime")?;
+    if lifetime.is_null() {
+        return Ok(None);
+    }
 
-    (coords - win.bottom_left()) / win.wh()
+    f32_field(snapshot, "lifetime").map(Some)
 }
@@ -0,0 +1,265 @@
+//! Exporting the paths particles travel as SVG polylines, for drawing them with a pen plotter.
+
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgAction, ArgMatches, Command, value_parser};
+use nannou::prelude::*;
+use crate::colormap::ColorAttribute;
+use crate::plot::optimize::PlotStats;
+use crate::plot::simplify::{drop_short_segments, simplify};
+use crate::render::RenderContext;
+use crate::scenes::parameters::{format_hex_color, Parameter, ParameterValue, ParameterValues};
+use crate::scenes::SceneInstance;
+
+pub mod optimize;
+pub mod simplify;
+pub mod svg;
+
+
+/// The amount of colors `export-svg` quantizes the palette to when the particles are colored by an attribute
+/// and no `color-steps` were given, since every color becomes a layer drawn with a pen of its own.
+pub const DEFAULT_COLOR_STEPS: i64 = 8;
+
+
+/// A path to be drawn with a single stroke, in painter coordinates.
+#[derive(Clone, Debug)]
+pub struct Trail {
+    pub points: Vec<Point2>,
+    pub color: Srgb<u8>,
+}
+
+/// The paths drawn with a single pen, which becomes a group of its own in the SVG.
+#[derive(Clone, Debug)]
+pub struct Layer {
+    pub color: Srgb<u8>,
+    pub paths: Vec<Vec<Point2>>,
+}
+
+/// Groups the trails into a layer per color, in the order the colors first appear.
+pub fn layers(trails: Vec<Trail>) -> Vec<Layer> {
+    let mut layers: Vec<Layer> = Vec::new();
+
+    for trail in trails {
+        match layers.iter_mut().find(|layer| layer.color == trail.color) {
+            Some(layer) => layer.paths.push(trail.points),
+            None => layers.push(Layer {
+                color: trail.color,
+                paths: vec![trail.points],
+            }),
+        }
+    }
+
+    layers
+}
+
+/// Quantizes the palette to `DEFAULT_COLOR_STEPS` colors when the scene colors its particles by an attribute
+/// and keeps the palette smooth, which would give nearly every particle a layer of its own.
+pub fn quantize_colors(parameters: &[Parameter], values: &mut ParameterValues) -> Result<(), String> {
+    let colored_by_attribute = matches!(
+        values.get("color-by"),
+        Some(ParameterValue::Choice(attribute)) if *attribute != ColorAttribute::Constant.name()
+    );
+
+    if colored_by_attribute && values.get("color-steps") == Some(&ParameterValue::Integer(0)) {
+        values.set(parameters, "color-steps", ParameterValue::Integer(DEFAULT_COLOR_STEPS))?;
+    }
+
+    Ok(())
+}
+
+
+/// A scene whose particles can record the paths they travel.
+/// Scenes that don't record anything keep the default methods.
+pub trait Plottable {
+    /// Starts recording, dropping points closer than `min_distance` to the previous one.
+    /// Returns `false` if the scene doesn't record trails.
+    fn record_trails(&mut self, _min_distance: f32) -> bool {
+        false
+    }
+
+    /// The trails recorded so far.
+    fn trails(&self) -> Vec<Trail> {
+        Vec::new()
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct PlotOptions {
+    /// The amount of frames to simulate before writing the trails.
+    pub frames: u64,
+    pub width: u32,
+    pub height: u32,
+    /// The frame rate the simulation is advanced at.
+    pub fps: f32,
+    /// How far the simplified lines may stray from the recorded ones, in pixels.
+    pub tolerance: f32,
+    /// Points closer than this to the previous one are dropped, in pixels.
+    pub min_segment_length: f32,
+    /// Paths whose endpoints lie closer than this are joined, in pixels.
+    pub join_tolerance: f32,
+    /// Paths shorter than this are dropped, in pixels.
+    pub min_path_length: f32,
+    /// Whether to reorder the paths to keep the pen-up travel short.
+    pub optimize: bool,
+    pub output: PathBuf,
+}
+
+impl PlotOptions {
+    /// The `export-svg` subcommand, which takes the scene as a subcommand of its own.
+    pub fn command() -> Command {
+        Command::new("export-svg")
+            .about("Simulates a scene without a window and writes the particle trails as SVG polylines for pen plotters. \
+                Particles colored by an attribute get 8 colors unless --color-steps picks another amount")
+            .subcommand_required(true)
+            .arg(Arg::new("frames")
+                .long("frames")
+                .help("The amount of frames to simulate")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("600"))
+            .arg(Arg::new("width")
+                .long("width")
+                .help("The width of the drawing in pixels")
+                .value_parser(value_parser!(u32).range(1..=16384))
+                .default_value("1800"))
+            .arg(Arg::new("height")
+                .long("height")
+                .help("The height of the drawing in pixels")
+                .value_parser(value_parser!(u32).range(1..=16384))
+                .default_value("1200"))
+            .arg(Arg::new("fps")
+                .long("fps")
+                .help("The frame rate the simulation is advanced at")
+                .value_parser(value_parser!(f32))
+                .default_value("60"))
+            .arg(Arg::new("tolerance")
+                .long("tolerance")
+                .help("How far the simplified lines may stray from the recorded ones, in pixels")
+                .value_parser(value_parser!(f32))
+                .default_value("0.5"))
+            .arg(Arg::new("min-segment-length")
+                .long("min-segment-length")
+                .help("Points closer than this to the previous one are dropped, in pixels")
+                .value_parser(value_parser!(f32))
+                .default_value("1.0"))
+            .arg(Arg::new("join-tolerance")
+                .long("join-tolerance")
+                .help("Paths whose endpoints lie closer than this are joined, in pixels")
+                .value_parser(value_parser!(f32))
+                .default_value("0.5"))
+            .arg(Arg::new("min-path-length")
+                .long("min-path-length")
+                .help("Paths shorter than this are dropped, in pixels")
+                .value_parser(value_parser!(f32))
+                .default_value("2.0"))
+            .arg(Arg::new("no-optimize")
+                .long("no-optimize")
+                .help("Keeps the paths in the order they were recorded instead of minimizing the pen-up travel")
+                .action(ArgAction::SetTrue))
+            .arg(Arg::new("output")
+                .long("output")
+                .help("The SVG file to write")
+                .value_parser(value_parser!(PathBuf))
+                .default_value("trails.svg"))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            frames: *matches.get_one::<u64>("frames").unwrap(),
+            width: *matches.get_one::<u32>("width").unwrap(),
+            height: *matches.get_one::<u32>("height").unwrap(),
+            fps: *matches.get_one::<f32>("fps").unwrap(),
+            tolerance: *matches.get_one::<f32>("tolerance").unwrap(),
+            min_segment_length: *matches.get_one::<f32>("min-segment-length").unwrap(),
+            join_tolerance: *matches.get_one::<f32>("join-tolerance").unwrap(),
+            min_path_length: *matches.get_one::<f32>("min-path-length").unwrap(),
+            optimize: !matches.get_flag("no-optimize"),
+            output: matches.get_one::<PathBuf>("output").unwrap().clone(),
+        }
+    }
+}
+
+/// Simulates the scene while recording its trails, then writes them simplified to an SVG file,
+/// with a layer per color. Returns the stats of every layer.
+pub fn export_svg(scene: &mut dyn SceneInstance, options: &PlotOptions) -> Result<Vec<(Srgb<u8>, PlotStats)>, String> {
+    if options.fps.is_nan() || options.fps <= 0.0 {
+        return Err(format!("The frame rate must be positive, got {}", options.fps));
+    }
+    let lengths = [options.tolerance, options.min_segment_length, options.join_tolerance, options.min_path_length];
+    if !lengths.iter().all(|length| *length >= 0.0) {
+        return Err("The tolerances and minimum lengths can't be negative".to_string());
+    }
+    if !scene.record_trails(options.min_segment_length) {
+        return Err("This scene doesn't record trails".to_string());
+    }
+
+    let mut context = RenderContext {
+        bounds: Rect::from_w_h(options.width as f32, options.height as f32),
+        frame_time: 1.0 / options.fps,
+        elapsed_frames: 0,
+    };
+
+    for frame in 0..options.frames {
+        context.elapsed_frames = frame;
+        scene.advance(&context);
+    }
+
+    let trails = scene.trails()
+        .into_iter()
+        .map(|trail| Trail {
+            points: drop_short_segments(&simplify(&trail.points, options.tolerance), options.min_segment_length),
+            color: trail.color,
+        })
+        .filter(|trail| trail.points.len() >= 2)
+        .collect::<Vec<_>>();
+
+    // The pen starts in the top left corner of the paper
+    let home = context.bounds.top_left();
+    let layers = layers(trails)
+        .into_iter()
+        .map(|layer| {
+            let mut paths = optimize::join(layer.paths, options.join_tolerance);
+            paths = optimize::drop_fragments(paths, options.min_path_length);
+            if options.optimize {
+                paths = optimize::order(paths, home);
+            }
+
+            Layer {
+                color: layer.color,
+                paths,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let document = svg::document(&layers, context.bounds);
+    fs::write(&options.output, document)
+        .map_err(|e| format!("Can't write {}: {}", options.output.display(), e))?;
+
+    Ok(layers.iter()
+        .map(|layer| (layer.color, PlotStats::of(&layer.paths, home)))
+        .collect())
+}
+
+/// A human-readable report of the distances drawn and travelled with the pen up, per layer and in total.
+pub fn describe_stats(stats: &[(Srgb<u8>, PlotStats)]) -> String {
+    let mut description = String::new();
+    let mut total = PlotStats::default();
+
+    for (index, (color, layer_stats)) in stats.iter().enumerate() {
+        description += &format!("Layer {} {}: {}\n", index + 1, format_hex_color(*color), describe_layer(layer_stats));
+        total += *layer_stats;
+    }
+    description += &format!("Total: {}\n", describe_layer(&total));
+
+    description
+}
+
+fn describe_layer(stats: &PlotStats) -> String {
+    format!(
+        "{} paths, drawing {:.0} px, travelling {:.0} px with the pen up",
+        stats.paths,
+        stats.draw_distance,
+        stats.travel_distance,
+    )
+}
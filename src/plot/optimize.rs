@@ -0,0 +1,401 @@
+//! Preparing paths for a pen plotter: joining, dropping fragments and ordering them to keep pen-up travel short.
+//! Works on the paths of a single layer, so the pen never changes while they are drawn.
+
+use std::collections::HashMap;
+use std::ops::AddAssign;
+
+use nannou::prelude::*;
+
+
+/// The maximum amount of passes of the 2-opt improvement.
+const MAX_TWO_OPT_PASSES: usize = 10;
+/// The most paths a run reversed by 2-opt holds, which keeps each pass linear in the amount of paths.
+const TWO_OPT_WINDOW: usize = 50;
+/// The smallest cell of the endpoint grid, so a join tolerance of 0 still divides the plane into cells.
+const MIN_CELL_SIZE: f32 = 1.0e-3;
+
+/// Joins paths whose endpoints lie within `tolerance` of each other, reversing them where needed.
+pub fn join(paths: Vec<Vec<Point2>>, tolerance: f32) -> Vec<Vec<Point2>> {
+    let mut remaining = paths.into_iter()
+        .filter(|path| !path.is_empty())
+        .map(Some)
+        .collect::<Vec<_>>();
+    let mut endpoints = Endpoints::new(&remaining, tolerance.max(MIN_CELL_SIZE));
+    let mut joined = Vec::new();
+
+    for i in 0..remaining.len() {
+        if remaining[i].is_none() {
+            continue;
+        }
+        let mut chain = endpoints.take(&mut remaining, i, false);
+
+        // Extends the chain at its end, then reverses it to extend the other end, then reverses it back
+        for _ in 0..2 {
+            while let Some((index, reverse)) = endpoints.nearest(&remaining, *chain.last().unwrap(), tolerance) {
+                let next = endpoints.take(&mut remaining, index, reverse);
+                chain.extend(next.into_iter().skip(1));
+            }
+            chain.reverse();
+        }
+
+        joined.push(chain);
+    }
+
+    joined
+}
+
+/// Drops the paths shorter than `min_length`, which would only be a dot on paper.
+pub fn drop_fragments(paths: Vec<Vec<Point2>>, min_length: f32) -> Vec<Vec<Point2>> {
+    paths.into_iter()
+        .filter(|path| path_length(path) >= min_length)
+        .collect()
+}
+
+/// Orders and orients the paths to keep the pen-up travel short, starting with the pen at `start`.
+/// Picks the nearest path greedily, then improves the order with 2-opt.
+/// The given order is kept when it's already shorter, so ordering never adds travel.
+pub fn order(paths: Vec<Vec<Point2>>, start: Point2) -> Vec<Vec<Point2>> {
+    let mut remaining = paths.into_iter()
+        .filter(|path| !path.is_empty())
+        .map(Some)
+        .collect::<Vec<_>>();
+    let mut endpoints = Endpoints::new(&remaining, order_cell_size(&remaining));
+    let mut ordered = Vec::with_capacity(remaining.len());
+    let mut position = start;
+
+    while let Some((index, reverse)) = endpoints.nearest(&remaining, position, f32::INFINITY) {
+        let path = endpoints.take(&mut remaining, index, reverse);
+        position = *path.last().unwrap();
+        ordered.push(path);
+    }
+
+    two_opt(&mut ordered, start);
+
+    let given = remaining.into_iter().flatten().collect::<Vec<_>>();
+    if given.is_empty() || PlotStats::of(&ordered, start).travel_distance <= PlotStats::of(&given, start).travel_distance {
+        ordered
+    } else {
+        given
+    }
+}
+
+/// Cells about as large as the spacing of the endpoints if they were spread evenly over their bounds.
+fn order_cell_size(paths: &[Option<Vec<Point2>>]) -> f32 {
+    let points = paths.iter()
+        .flatten()
+        .flat_map(|path| [path[0], *path.last().unwrap()])
+        .collect::<Vec<_>>();
+    let Some(&first) = points.first() else {
+        return MIN_CELL_SIZE;
+    };
+
+    let (min, max) = points.iter().fold((first, first), |(min, max), point| (min.min(*point), max.max(*point)));
+    let size = (max - min).max_element();
+    (size / (points.len() as f32).sqrt()).max(MIN_CELL_SIZE)
+}
+
+/// Reverses runs of paths, flipping each path in the run, wherever that shortens the travel.
+/// Only the travel into and out of the run changes, the travel between its paths stays the same.
+/// Runs hold at most `TWO_OPT_WINDOW` paths, the greedy order already keeps paths near each other close in it.
+fn two_opt(paths: &mut [Vec<Point2>], start: Point2) {
+    for _ in 0..MAX_TWO_OPT_PASSES {
+        let mut improved = false;
+
+        for i in 0..paths.len() {
+            let before = if i == 0 { start } else { *paths[i - 1].last().unwrap() };
+
+            for j in i..paths.len().min(i + TWO_OPT_WINDOW) {
+                let first = paths[i][0];
+                let last = *paths[j].last().unwrap();
+                let after = paths.get(j + 1).map(|path| path[0]);
+
+                let current = before.distance(first) + after.map_or(0.0, |after| last.distance(after));
+                let reversed = before.distance(last) + after.map_or(0.0, |after| first.distance(after));
+
+                if reversed + f32::EPSILON < current {
+                    paths[i..=j].reverse();
+                    paths[i..=j].iter_mut().for_each(|path| path.reverse());
+                    improved = true;
+                }
+            }
+        }
+
+        if !improved {
+            break;
+        }
+    }
+}
+
+fn path_length(path: &[Point2]) -> f32 {
+    path.windows(2)
+        .map(|segment| segment[0].distance(segment[1]))
+        .sum()
+}
+
+
+/// The endpoints of the paths not taken yet, hashed by the cell of a square grid they lie in,
+/// so finding the ones near a point only looks at the cells around it.
+struct Endpoints {
+    cell_size: f32,
+    /// The paths with an endpoint in each cell, and whether that endpoint is their end.
+    cells: HashMap<(i32, i32), Vec<(usize, bool)>>,
+}
+
+impl Endpoints {
+    fn new(paths: &[Option<Vec<Point2>>], cell_size: f32) -> Self {
+        let mut endpoints = Self {
+            cell_size,
+            cells: HashMap::new(),
+        };
+
+        for (index, path) in paths.iter().enumerate() {
+            let Some(path) = path else {
+                continue;
+            };
+            for (point, reverse) in [(path[0], false), (*path.last().unwrap(), true)] {
+                endpoints.cells.entry(endpoints.cell(point)).or_default().push((index, reverse));
+            }
+        }
+
+        endpoints
+    }
+
+    fn cell(&self, point: Point2) -> (i32, i32) {
+        ((point.x / self.cell_size).floor() as i32, (point.y / self.cell_size).floor() as i32)
+    }
+
+    /// Takes the path out of the slots and its endpoints out of the grid, oriented to start at its end when `reverse` is set.
+    fn take(&mut self, paths: &mut [Option<Vec<Point2>>], index: usize, reverse: bool) -> Vec<Point2> {
+        let mut path = paths[index].take().unwrap();
+
+        for point in [path[0], *path.last().unwrap()] {
+            let cell = self.cell(point);
+            if let Some(entries) = self.cells.get_mut(&cell) {
+                entries.retain(|(i, _)| *i != index);
+                if entries.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+
+        if reverse {
+            path.reverse();
+        }
+        path
+    }
+
+    /// The path with the endpoint closest to the point within `max_distance`, and whether that endpoint is its end.
+    /// Ties go to the lowest index, so the result doesn't depend on the order of the hash map.
+    ///
+    /// Looks through the rings of cells around the point until the next ring can't hold anything closer,
+    /// or through all endpoints once the rings cover more cells than hold any.
+    fn nearest(&self, paths: &[Option<Vec<Point2>>], point: Point2, max_distance: f32) -> Option<(usize, bool)> {
+        let (x, y) = self.cell(point);
+        let mut best: Option<(f32, usize, bool)> = None;
+        let mut looked_at = 0;
+
+        for ring in 0i32.. {
+            // The endpoints in this ring and beyond lie at least this far away
+            let min_distance = (ring - 1) as f32 * self.cell_size;
+            if min_distance > max_distance || best.is_some_and(|(distance, _, _)| distance < min_distance) {
+                break;
+            }
+
+            if looked_at >= self.cells.len() {
+                let entries = self.cells.values().flatten();
+                best = self.closest(entries, paths, point, max_distance);
+                break;
+            }
+
+            let cells = (-ring..=ring)
+                .flat_map(|dx| (-ring..=ring).map(move |dy| (dx, dy)))
+                .filter(|(dx, dy): &(i32, i32)| dx.abs() == ring || dy.abs() == ring);
+            for (dx, dy) in cells {
+                looked_at += 1;
+                if let Some(entries) = self.cells.get(&(x + dx, y + dy)) {
+                    let closest = self.closest(entries.iter(), paths, point, max_distance);
+                    best = best.into_iter().chain(closest).min_by(compare);
+                }
+            }
+        }
+
+        best.map(|(_, index, reverse)| (index, reverse))
+    }
+
+    fn closest<'a>(&self, entries: impl Iterator<Item = &'a (usize, bool)>, paths: &[Option<Vec<Point2>>], point: Point2, max_distance: f32) -> Option<(f32, usize, bool)> {
+        entries
+            .map(|&(index, reverse)| {
+                let path = paths[index].as_ref().unwrap();
+                let endpoint = if reverse { *path.last().unwrap() } else { path[0] };
+                (endpoint.distance(point), index, reverse)
+            })
+            .filter(|(distance, _, _)| *distance <= max_distance)
+            .min_by(compare)
+    }
+}
+
+fn compare(a: &(f32, usize, bool), b: &(f32, usize, bool)) -> std::cmp::Ordering {
+    a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2))
+}
+
+
+/// How much a plot draws and how far the pen moves up between paths, in pixels.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct PlotStats {
+    pub paths: usize,
+    pub draw_distance: f32,
+    pub travel_distance: f32,
+}
+
+impl PlotStats {
+    /// The stats of drawing the paths in order, starting with the pen at `start`.
+    pub fn of(paths: &[Vec<Point2>], start: Point2) -> Self {
+        let mut position = start;
+        let mut stats = Self::default();
+
+        for path in paths.iter().filter(|path| !path.is_empty()) {
+            stats.paths += 1;
+            stats.draw_distance += path_length(path);
+            stats.travel_distance += position.distance(path[0]);
+            position = *path.last().unwrap();
+        }
+
+        stats
+    }
+}
+
+impl AddAssign for PlotStats {
+    fn add_assign(&mut self, other: Self) {
+        self.paths += other.paths;
+        self.draw_distance += other.draw_distance;
+        self.travel_distance += other.travel_distance;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use super::*;
+    use crate::simulation::SceneRng;
+
+    fn path(points: &[(f32, f32)]) -> Vec<Point2> {
+        points.iter().map(|&(x, y)| pt2(x, y)).collect()
+    }
+
+    /// Short random strokes scattered over a square.
+    fn scattered_paths(seed: u64, count: usize) -> Vec<Vec<Point2>> {
+        let mut rng = SceneRng::seed_from_u64(seed);
+        (0..count)
+            .map(|_| {
+                let start = pt2(rng.gen_range(-500.0..500.0), rng.gen_range(-500.0..500.0));
+                let end = start + vec2(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0));
+                vec![start, end]
+            })
+            .collect()
+    }
+
+    #[test]
+    fn paths_with_matching_endpoints_are_joined() {
+        let paths = vec![
+            path(&[(0.0, 0.0), (1.0, 0.0)]),
+            path(&[(10.0, 10.0), (11.0, 10.0)]),
+            // Runs backwards from the end of the first
+            path(&[(2.0, 0.0), (1.0, 0.0)]),
+            // Ends within the tolerance of the start of the first
+            path(&[(-1.0, 0.0), (0.05, 0.0)]),
+            path(&[(2.0, 0.0), (3.0, 0.0)]),
+        ];
+
+        let joined = join(paths, 0.1);
+
+        assert_eq!(joined.len(), 2, "{:?}", joined);
+        let mut chain = joined[0].clone();
+        if chain[0].x > 0.0 {
+            chain.reverse();
+        }
+        assert_eq!(chain, path(&[(-1.0, 0.0), (0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)]));
+        assert_eq!(joined[1], path(&[(10.0, 10.0), (11.0, 10.0)]));
+    }
+
+    #[test]
+    fn paths_further_apart_than_the_tolerance_stay_apart() {
+        let paths = vec![
+            path(&[(0.0, 0.0), (1.0, 0.0)]),
+            path(&[(1.2, 0.0), (2.0, 0.0)]),
+        ];
+
+        assert_eq!(join(paths.clone(), 0.1), paths);
+        assert_eq!(join(paths, 0.0).len(), 2);
+    }
+
+    #[test]
+    fn fragments_shorter_than_the_threshold_are_dropped() {
+        let paths = vec![
+            path(&[(0.0, 0.0), (0.3, 0.4)]),
+            path(&[(5.0, 5.0)]),
+            path(&[(0.0, 0.0), (0.6, 0.8), (1.2, 1.6)]),
+            path(&[(0.0, 0.0), (0.6, 0.8)]),
+        ];
+
+        let kept = drop_fragments(paths, 1.0);
+
+        assert_eq!(kept, vec![path(&[(0.0, 0.0), (0.6, 0.8), (1.2, 1.6)]), path(&[(0.0, 0.0), (0.6, 0.8)])]);
+    }
+
+    #[test]
+    fn ordering_never_adds_travel() {
+        for seed in 0..20 {
+            let paths = scattered_paths(seed, 200);
+            let before = PlotStats::of(&paths, Point2::ZERO);
+
+            let ordered = order(paths, Point2::ZERO);
+            let after = PlotStats::of(&ordered, Point2::ZERO);
+
+            assert_eq!(after.paths, before.paths);
+            assert!((after.draw_distance - before.draw_distance).abs() < 1.0e-2);
+            assert!(after.travel_distance <= before.travel_distance, "seed {}: {} > {}", seed, after.travel_distance, before.travel_distance);
+        }
+
+        // Already in the best order
+        let paths = vec![
+            path(&[(1.0, 0.0), (2.0, 0.0)]),
+            path(&[(3.0, 0.0), (4.0, 0.0)]),
+        ];
+        assert_eq!(order(paths.clone(), Point2::ZERO), paths);
+    }
+
+    #[test]
+    fn two_opt_uncrosses_the_travel() {
+        // Along a line from the origin, but the last two are visited in the wrong order
+        let mut paths = vec![
+            path(&[(1.0, 0.0), (2.0, 0.0)]),
+            path(&[(5.0, 0.0), (6.0, 0.0)]),
+            path(&[(3.0, 0.0), (4.0, 0.0)]),
+        ];
+        assert_eq!(PlotStats::of(&paths, Point2::ZERO).travel_distance, 7.0);
+
+        two_opt(&mut paths, Point2::ZERO);
+
+        assert!(PlotStats::of(&paths, Point2::ZERO).travel_distance < 7.0, "{:?}", paths);
+    }
+
+    #[test]
+    fn stats_add_up_the_drawing_and_the_travel() {
+        let paths = vec![
+            path(&[(0.0, 0.0), (3.0, 4.0)]),
+            Vec::new(),
+            path(&[(6.0, 8.0), (6.0, 9.0), (6.0, 11.0)]),
+        ];
+
+        let mut stats = PlotStats::of(&paths, pt2(0.0, -1.0));
+        assert_eq!(stats.paths, 2);
+        assert_eq!(stats.draw_distance, 8.0);
+        assert_eq!(stats.travel_distance, 6.0);
+
+        stats += PlotStats::of(&paths[2..], Point2::ZERO);
+        assert_eq!(stats.paths, 3);
+        assert_eq!(stats.draw_distance, 11.0);
+        assert_eq!(stats.travel_distance, 16.0);
+    }
+}
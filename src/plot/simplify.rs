@@ -0,0 +1,72 @@
+//! Reducing the amount of points in a line, so a plotter doesn't stutter through thousands of tiny moves.
+
+use nannou::prelude::*;
+
+
+/// Simplifies the line with the Ramer-Douglas-Peucker algorithm,
+/// keeping every point that lies further than `tolerance` from the simplified line.
+pub fn simplify(points: &[Point2], tolerance: f32) -> Vec<Point2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // An explicit stack instead of recursion, as recorded trails can have many thousands of points
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| (i, distance_to_segment(points[i], points[start], points[end])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.push((start, i));
+                ranges.push((i, end));
+            }
+        }
+    }
+
+    points.iter()
+        .zip(keep)
+        .filter_map(|(point, keep)| keep.then_some(*point))
+        .collect()
+}
+
+/// Drops the points closer than `min_length` to the previous kept point.
+/// The last point is always kept, replacing the kept point before it if that one is too close.
+pub fn drop_short_segments(points: &[Point2], min_length: f32) -> Vec<Point2> {
+    let mut kept: Vec<Point2> = Vec::with_capacity(points.len());
+
+    for point in points {
+        match kept.last() {
+            Some(last) if last.distance(*point) < min_length => {},
+            _ => kept.push(*point),
+        }
+    }
+
+    if let (Some(last), Some(kept_last)) = (points.last(), kept.last()) {
+        if kept_last != last {
+            if kept.len() > 1 {
+                kept.pop();
+            }
+            kept.push(*last);
+        }
+    }
+
+    kept
+}
+
+fn distance_to_segment(point: Point2, start: Point2, end: Point2) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+    if length_squared == 0.0 {
+        return point.distance(start);
+    }
+
+    let along = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + segment * along)
+}
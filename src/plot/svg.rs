@@ -0,0 +1,55 @@
+//! Writing trails as an SVG document.
+
+use std::fmt::Write;
+
+use nannou::prelude::*;
+use crate::plot::Layer;
+use crate::scenes::parameters::format_hex_color;
+
+
+/// The stroke width of the polylines, in pixels. A plotter draws with the width of its pen anyway.
+const STROKE_WIDTH: f32 = 1.0;
+
+/// Writes the layers as groups of polylines, with the drawing's bounds mapped onto the document.
+/// The groups are marked as Inkscape layers, named by their number and color, so plotter software can draw them one pen at a time.
+/// SVG has its origin in the top left with y pointing down, so the painter coordinates are flipped.
+pub fn document(layers: &[Layer], bounds: Rect) -> String {
+    let mut svg = String::new();
+
+    writeln!(svg, r#"<?xml version="1.0" encoding="UTF-8"?>"#).unwrap();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:inkscape="http://www.inkscape.org/namespaces/inkscape" width="{w}px" height="{h}px" viewBox="0 0 {w} {h}">"#,
+        w = bounds.w(),
+        h = bounds.h(),
+    ).unwrap();
+
+    for (index, layer) in layers.iter().enumerate() {
+        let color = format_hex_color(layer.color);
+        writeln!(
+            svg,
+            r#"  <g id="layer{n}" inkscape:groupmode="layer" inkscape:label="{n} {color}" fill="none" stroke="{color}" stroke-width="{width}" stroke-linecap="round" stroke-linejoin="round">"#,
+            n = index + 1,
+            color = color,
+            width = STROKE_WIDTH,
+        ).unwrap();
+
+        for path in layer.paths.iter() {
+            writeln!(svg, "    {}", polyline(path, bounds)).unwrap();
+        }
+
+        writeln!(svg, "  </g>").unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+fn polyline(path: &[Point2], bounds: Rect) -> String {
+    let points = path.iter()
+        .map(|point| format!("{:.2},{:.2}", point.x - bounds.left(), bounds.top() - point.y))
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    format!(r#"<polyline points="{}"/>"#, points)
+}
@@ -0,0 +1,269 @@
+//! A software rasterizer for the `Painter` primitives, for rendering on machines without a GPU.
+
+use std::ops::Range;
+
+use nannou::color::Alpha;
+use nannou::image::{self, RgbaImage};
+use nannou::prelude::*;
+use crate::render::Painter;
+
+
+/// An RGB buffer that blends in linear light, like nannou's frames do.
+/// Edges are anti-aliased by the fraction of each pixel a shape covers.
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    /// Linear RGB, row by row from the top.
+    pixels: Vec<[f32; 3]>,
+}
+
+impl Canvas {
+    /// Creates a black canvas.
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 3]; width as usize * height as usize],
+        }
+    }
+
+    /// The area covered by the canvas, centered on the origin.
+    pub fn bounds(&self) -> Rect {
+        Rect::from_w_h(self.width as f32, self.height as f32)
+    }
+
+    pub fn to_image(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let [red, green, blue] = self.pixels[(y * self.width + x) as usize];
+            image::Rgba([encode(red), encode(green), encode(blue), u8::MAX])
+        })
+    }
+
+    /// The center of a pixel in painter coordinates.
+    fn pixel_center(&self, x: usize, y: usize) -> Point2 {
+        pt2(
+            x as f32 + 0.5 - self.width as f32 / 2.0,
+            self.height as f32 / 2.0 - (y as f32 + 0.5),
+        )
+    }
+
+    /// The pixels overlapping the given area, clipped to the canvas.
+    fn pixel_ranges(&self, area: Rect) -> (Range<usize>, Range<usize>) {
+        let half_width = self.width as f32 / 2.0;
+        let half_height = self.height as f32 / 2.0;
+
+        let clip = |start: f32, end: f32, size: u32| {
+            let start = start.floor().clamp(0.0, size as f32) as usize;
+            let end = end.ceil().clamp(0.0, size as f32) as usize;
+            start..end
+        };
+
+        (
+            clip(area.left() + half_width, area.right() + half_width, self.width),
+            clip(half_height - area.top(), half_height - area.bottom(), self.height),
+        )
+    }
+
+    /// Blends the color over every pixel in the area, weighted by the coverage the function gives for its center.
+    fn fill<F>(&mut self, area: Rect, color: Srgba, coverage: F)
+        where
+            F: Fn(Point2) -> f32
+    {
+        let linear = [decode(color.color.red), decode(color.color.green), decode(color.color.blue)];
+        let (xs, ys) = self.pixel_ranges(area);
+
+        for y in ys {
+            for x in xs.clone() {
+                let alpha = color.alpha * coverage(self.pixel_center(x, y));
+                if alpha <= 0.0 {
+                    continue;
+                }
+
+                let pixel = &mut self.pixels[y * self.width as usize + x];
+                for (channel, value) in pixel.iter_mut().zip(linear) {
+                    *channel += (value - *channel) * alpha.min(1.0);
+                }
+            }
+        }
+    }
+}
+
+impl Painter for Canvas {
+    fn clear(&mut self, color: Srgba) {
+        let linear = [decode(color.color.red), decode(color.color.green), decode(color.color.blue)];
+        self.pixels.fill(linear);
+    }
+
+    fn ellipse(&mut self, center: Point2, radius: f32, color: Srgba) {
+        // Circles smaller than a pixel are drawn a pixel wide and fainter, keeping their area
+        let drawn_radius = radius.max(0.5);
+        let faded = Alpha {
+            color: color.color,
+            alpha: color.alpha * (radius / drawn_radius).powi(2),
+        };
+
+        let area = Rect::from_xy_wh(center, Vec2::splat(drawn_radius * 2.0 + 1.0));
+        self.fill(area, faded, |point| {
+            (drawn_radius + 0.5 - point.distance(center)).clamp(0.0, 1.0)
+        });
+    }
+
+    fn line(&mut self, start: Point2, end: Point2, weight: f32, color: Srgba) {
+        let length = start.distance(end);
+        if length <= f32::EPSILON {
+            return;
+        }
+
+        // Lines thinner than a pixel are drawn a pixel wide and fainter, like small circles
+        let half_weight = weight.max(1.0) / 2.0;
+        let faded = Alpha {
+            color: color.color,
+            alpha: color.alpha * weight.min(1.0),
+        };
+
+        let direction = (end - start) / length;
+        let padding = Vec2::splat(half_weight + 1.0);
+        let area = Rect::from_corners(start.min(end) - padding, start.max(end) + padding);
+
+        self.fill(area, faded, |point| {
+            let offset = point - start;
+            let along = offset.dot(direction);
+            let across = offset.perp_dot(direction).abs();
+
+            // Butt caps, like nannou's lines
+            (half_weight + 0.5 - across).clamp(0.0, 1.0)
+                * (along + 0.5).clamp(0.0, 1.0)
+                * (length - along + 0.5).clamp(0.0, 1.0)
+        });
+    }
+
+    fn rect(&mut self, rect: Rect, color: Srgba) {
+        let area = rect.pad(-1.0);
+        self.fill(area, color, |point| {
+            let overlap = |center: f32, start: f32, end: f32| {
+                ((center + 0.5).min(end) - (center - 0.5).max(start)).clamp(0.0, 1.0)
+            };
+
+            overlap(point.x, rect.left(), rect.right()) * overlap(point.y, rect.bottom(), rect.top())
+        });
+    }
+}
+
+
+/// The sRGB transfer function, from an encoded channel to linear light.
+fn decode(channel: f32) -> f32 {
+    if channel <= 0.04045 {
+        channel / 12.92
+    } else {
+        ((channel + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// From linear light to an 8 bit sRGB channel.
+fn encode(channel: f32) -> u8 {
+    let channel = channel.clamp(0.0, 1.0);
+    let encoded = if channel <= 0.0031308 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    };
+
+    (encoded * 255.0).round() as u8
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white(alpha: f32) -> Srgba {
+        rgba(1.0, 1.0, 1.0, alpha)
+    }
+
+    /// The red channel of the pixel in the image, the tests only draw in white on black.
+    fn pixel(canvas: &Canvas, x: u32, y: u32) -> u8 {
+        canvas.to_image().get_pixel(x, y).0[0]
+    }
+
+    /// The amount of white on the canvas, in pixels.
+    fn coverage(canvas: &Canvas) -> f32 {
+        canvas.pixels.iter().map(|pixel| pixel[0]).sum()
+    }
+
+    #[test]
+    fn ellipses_cover_their_area() {
+        let mut canvas = Canvas::new(20, 20);
+        canvas.ellipse(Point2::ZERO, 5.0, white(1.0));
+
+        // Pixel centers lie half a pixel off the origin
+        assert_eq!(pixel(&canvas, 10, 10), 255);
+        assert_eq!(pixel(&canvas, 12, 12), 255);
+        assert_eq!(pixel(&canvas, 0, 0), 0);
+        assert_eq!(pixel(&canvas, 16, 10), 0);
+        assert!(pixel(&canvas, 14, 10) > 0 && pixel(&canvas, 14, 10) < 255);
+        assert!((coverage(&canvas) - PI * 25.0).abs() < 0.02 * PI * 25.0, "covers {}", coverage(&canvas));
+    }
+
+    #[test]
+    fn circles_smaller_than_a_pixel_fade_with_their_area() {
+        let coverage_of = |radius: f32| {
+            let mut canvas = Canvas::new(10, 10);
+            canvas.ellipse(pt2(0.3, 0.2), radius, white(1.0));
+            coverage(&canvas)
+        };
+
+        // Drawn as large as a circle with a radius of half a pixel
+        let ratio = coverage_of(0.25) / coverage_of(0.5);
+        assert!((ratio - 0.25).abs() < 1.0e-4, "ratio {}", ratio);
+    }
+
+    #[test]
+    fn lines_end_at_their_endpoints() {
+        let mut canvas = Canvas::new(20, 10);
+        canvas.line(pt2(-5.0, 0.0), pt2(5.0, 0.0), 2.0, white(1.0));
+
+        // The rows either side of y = 0, from x = -5 to 5
+        for y in [4, 5] {
+            assert_eq!(pixel(&canvas, 4, y), 0);
+            for x in 5..15 {
+                assert_eq!(pixel(&canvas, x, y), 255, "at {}, {}", x, y);
+            }
+            assert_eq!(pixel(&canvas, 15, y), 0);
+        }
+        assert_eq!(pixel(&canvas, 10, 3), 0);
+        assert_eq!(pixel(&canvas, 10, 6), 0);
+        assert!((coverage(&canvas) - 20.0).abs() < 1.0e-3, "covers {}", coverage(&canvas));
+    }
+
+    #[test]
+    fn colors_blend_in_linear_light() {
+        let mut canvas = Canvas::new(4, 4);
+        canvas.rect(canvas.bounds(), white(0.5));
+
+        // Half of white in linear light is 188 in sRGB, blending the encoded values would give 128
+        assert_eq!(pixel(&canvas, 1, 1), 188);
+        assert_eq!(encode(decode(0.5)), 128);
+        assert_eq!(encode(0.5), 188);
+    }
+
+    #[test]
+    fn shapes_are_clipped_at_the_edges() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.ellipse(pt2(5.0, 5.0), 3.0, white(1.0));
+
+        // A quarter of the circle lies on the canvas, in its top right corner
+        assert_eq!(pixel(&canvas, 9, 0), 255);
+        assert_eq!(pixel(&canvas, 0, 9), 0);
+        assert!((coverage(&canvas) - PI * 9.0 / 4.0).abs() < 0.5, "covers {}", coverage(&canvas));
+
+        // Entirely off the canvas
+        let mut canvas = Canvas::new(10, 10);
+        canvas.line(pt2(-100.0, -100.0), pt2(-50.0, 100.0), 5.0, white(1.0));
+        canvas.ellipse(pt2(100.0, 0.0), 20.0, white(1.0));
+        assert_eq!(coverage(&canvas), 0.0);
+
+        let mut canvas = Canvas::new(10, 10);
+        canvas.rect(Rect::from_w_h(100.0, 100.0), white(1.0));
+        assert_eq!(coverage(&canvas), 100.0);
+    }
+}
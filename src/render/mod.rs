@@ -0,0 +1,155 @@
+//! Drawing the scenes independently of nannou's window, so frames can also be rendered headless on the CPU.
+
+use std::fs;
+use std::path::PathBuf;
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use nannou::color::Alpha;
+use nannou::prelude::*;
+use crate::render::canvas::Canvas;
+use crate::scenes::SceneInstance;
+
+pub mod canvas;
+
+
+/// The primitives the scenes draw with.
+/// Coordinates are in pixels with the origin in the center and y pointing up, like nannou's window.
+pub trait Painter {
+    /// Fills the whole area with the color.
+    fn clear(&mut self, color: Srgba);
+    fn ellipse(&mut self, center: Point2, radius: f32, color: Srgba);
+    fn line(&mut self, start: Point2, end: Point2, weight: f32, color: Srgba);
+    fn rect(&mut self, rect: Rect, color: Srgba);
+}
+
+impl Painter for Draw {
+    fn clear(&mut self, color: Srgba) {
+        self.background().color(color);
+    }
+
+    fn ellipse(&mut self, center: Point2, radius: f32, color: Srgba) {
+        Draw::ellipse(self).radius(radius).color(color).xy(center);
+    }
+
+    fn line(&mut self, start: Point2, end: Point2, weight: f32, color: Srgba) {
+        Draw::line(self).start(start).end(end).color(color).stroke_weight(weight);
+    }
+
+    fn rect(&mut self, rect: Rect, color: Srgba) {
+        Draw::rect(self).wh(rect.wh()).xy(rect.xy()).color(color);
+    }
+}
+
+
+/// What a scene needs to know about the frame it is advancing and drawing,
+/// filled in from the `App` in a window or from the render options when headless.
+#[derive(Copy, Clone, Debug)]
+pub struct RenderContext {
+    /// The area being drawn on, the window rect in a window.
+    pub bounds: Rect,
+    /// The wall clock time since the previous frame, in seconds.
+    pub frame_time: f32,
+    pub elapsed_frames: u64,
+}
+
+impl RenderContext {
+    pub fn from_app(app: &App) -> Self {
+        Self {
+            bounds: app.window_rect(),
+            frame_time: app.duration.since_prev_update.as_secs_f32(),
+            elapsed_frames: app.elapsed_frames(),
+        }
+    }
+}
+
+
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub frames: u64,
+    pub width: u32,
+    pub height: u32,
+    /// The frame rate the simulation is advanced at.
+    pub fps: f32,
+    /// The directory the frames are written to.
+    pub output: PathBuf,
+}
+
+impl RenderOptions {
+    /// The `render` subcommand, which takes the scene as a subcommand of its own.
+    pub fn command() -> Command {
+        Command::new("render")
+            .about("Renders a scene to PNG frames on the CPU, without opening a window")
+            .subcommand_required(true)
+            .arg(Arg::new("frames")
+                .long("frames")
+                .help("The amount of frames to render")
+                .value_parser(value_parser!(u64).range(1..))
+                .default_value("1"))
+            .arg(Arg::new("width")
+                .long("width")
+                .help("The width of the frames in pixels")
+                .value_parser(value_parser!(u32).range(1..=16384))
+                .default_value("1800"))
+            .arg(Arg::new("height")
+                .long("height")
+                .help("The height of the frames in pixels")
+                .value_parser(value_parser!(u32).range(1..=16384))
+                .default_value("1200"))
+            .arg(Arg::new("fps")
+                .long("fps")
+                .help("The frame rate the simulation is advanced at")
+                .value_parser(value_parser!(f32))
+                .default_value("60"))
+            .arg(Arg::new("output")
+                .long("output")
+                .help("The directory the frames are written to")
+                .value_parser(value_parser!(PathBuf))
+                .default_value("frames"))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            frames: *matches.get_one::<u64>("frames").unwrap(),
+            width: *matches.get_one::<u32>("width").unwrap(),
+            height: *matches.get_one::<u32>("height").unwrap(),
+            fps: *matches.get_one::<f32>("fps").unwrap(),
+            output: matches.get_one::<PathBuf>("output").unwrap().clone(),
+        }
+    }
+}
+
+/// Advances and draws the scene frame by frame, writing every frame as `frame-00000.png` and onwards.
+/// Like in a window, the canvas is only cleared once, so trails build up over the frames.
+pub fn render(scene: &mut dyn SceneInstance, options: &RenderOptions) -> Result<(), String> {
+    if options.fps.is_nan() || options.fps <= 0.0 {
+        return Err(format!("The frame rate must be positive, got {}", options.fps));
+    }
+
+    fs::create_dir_all(&options.output)
+        .map_err(|e| format!("Can't create output directory {}: {}", options.output.display(), e))?;
+
+    let mut canvas = Canvas::new(options.width, options.height);
+    canvas.clear(Alpha {
+        color: scene.background().into_format(),
+        alpha: 1.0,
+    });
+
+    let mut context = RenderContext {
+        bounds: canvas.bounds(),
+        frame_time: 1.0 / options.fps,
+        elapsed_frames: 0,
+    };
+
+    for frame in 0..options.frames {
+        context.elapsed_frames = frame;
+        scene.advance(&context);
+        scene.draw(&context, &mut canvas, 1.0);
+
+        let path = options.output.join(format!("frame-{:05}.png", frame));
+        canvas.to_image()
+            .save(&path)
+            .map_err(|e| format!("Can't write frame {}: {}", path.display(), e))?;
+    }
+
+    Ok(())
+}
@@ -4,33 +4,160 @@ use nannou::prelude::*;
 use nannou::{App, Frame};
 use nannou::app::Builder;
 use nannou::color::Alpha;
-use nannou::glam::Vec4Swizzles;
 use async_trait::async_trait;
 use nannou::wgpu::{DeviceDescriptor, Limits};
+use serde_json::{json, Value};
 use crate::math_3d::Camera;
-use crate::math_3d::controls::{CameraControls, CenteredCameraControls, MouseBasedCenteredCameraControls};
+use crate::math_3d::clip::project_point;
+use crate::math_3d::controls::{CameraControls, MouseBasedCenteredCameraControls};
+use crate::math_3d::depth::{back_to_front, DepthCue, DepthCueOptions};
+use crate::palette::Palette;
 use crate::particle::Particle3;
-use crate::scenes::Scene;
+use crate::plot::Plottable;
+use crate::render::{Painter, RenderContext};
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::{ModelInstance, Scene, SceneInstance, WindowOptions};
+use crate::simulation::Simulation;
+use crate::snapshot::{field, restore_all, restored, Snapshot, snapshot_all, SnapshotFile};
+use crate::timestep::{FixedTimestep, TimestepOptions};
 
 
-const BACKGROUND_COLOR: Srgb<u8> = BLANCHEDALMOND;
-
 pub struct Base3DScene {
-    model_fn: fn(app: &App) -> Model,
+    options: Base3DOptions,
     update_fn: fn(app: &App, model: &mut Model, _update: Update),
-    view_fn: fn(app: &App, model: &Model, frame: Frame),
+    advance_fn: fn(model: &mut Model, context: &RenderContext),
+    draw_fn: fn(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32),
     event_fn: fn(app: &App, model: &mut Model, event: Event),
+    config: Option<ConfigWatcher>,
+    snapshots: Option<SnapshotFile>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Base3DOptions {
+    /// The seed for the scene's RNG, a random one is picked when `None`.
+    /// The cube is static, so this is only kept for parity with the other scenes.
+    pub seed: Option<u32>,
+    pub particle_color: Srgb<u8>,
+    /// The palette the background is picked from, the cube's corners keep their own color.
+    pub palette: Palette,
+    /// The palette's background is used when it isn't set.
+    pub background_color: Option<Srgb<u8>>,
+    /// Fades and shrinks the corners further from the camera.
+    pub depth_cue: DepthCueOptions,
+    pub window: WindowOptions,
+    pub timestep: TimestepOptions,
+}
+
+impl Default for Base3DOptions {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            particle_color: BLACK,
+            palette: Palette::named("parchment").unwrap(),
+            background_color: None,
+            depth_cue: DepthCueOptions::default(),
+            window: WindowOptions {
+                width: 1024,
+                height: 768,
+            },
+            timestep: TimestepOptions::default(),
+        }
+    }
+}
+
+impl Base3DOptions {
+    pub fn parameters() -> Vec<Parameter> {
+        let defaults = Self::default();
+        let mut parameters = vec![
+            Parameter::seed(),
+            Parameter::color("particle-color", "The color of the cube's corners", defaults.particle_color),
+            Parameter::palette("palette", "The palette the background is picked from", defaults.palette),
+            Parameter::optional_color("background-color", "The color of the background, the palette's background if not given"),
+        ];
+        parameters.extend(DepthCueOptions::parameters(defaults.depth_cue));
+        parameters.extend(WindowOptions::parameters(defaults.window));
+        parameters.extend(TimestepOptions::parameters(defaults.timestep));
+
+        parameters
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            seed: values.seed(),
+            particle_color: values.color("particle-color"),
+            palette: values.palette("palette"),
+            background_color: values.optional_color("background-color"),
+            depth_cue: DepthCueOptions::from_parameters(values),
+            window: WindowOptions::from_parameters(values),
+            timestep: TimestepOptions::from_parameters(values),
+        }
+    }
+
+    /// The background color, which comes from the palette unless it is set.
+    pub fn background(&self) -> Srgb<u8> {
+        self.background_color.unwrap_or(self.palette.background)
+    }
 }
 
 pub struct Model {
+    options: Base3DOptions,
     camera: Camera,
     camera_controls: Box<dyn CameraControls>,
-    points: Vec<Particle3>
+    timestep: FixedTimestep,
+    simulation: Base3DSimulation,
+    /// The config file whose changes are applied while running, if any.
+    config: Option<ConfigWatcher>,
+    snapshots: SnapshotFile,
+}
+
+/// The window-free part of the Base3D scene: the corners of a cube that stay in place.
+pub struct Base3DSimulation {
+    pub points: Vec<Particle3>,
+}
+
+impl Plottable for Model {}
+
+/// The options aren't written, a snapshot is restored into a scene that was started with them.
+impl Snapshot for Model {
+    fn snapshot(&self) -> Value {
+        json!({
+            "timestep": self.timestep.snapshot(),
+            "camera": self.camera.snapshot(),
+            "points": snapshot_all(&self.simulation.points),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let timestep = restored(&self.timestep, snapshot, "timestep")?;
+        let camera = Camera::from_snapshot(field(snapshot, "camera")?)?;
+        let points = restore_all(snapshot, "points")?;
+
+        self.timestep = timestep;
+        self.camera = camera;
+        self.simulation.points = points;
+
+        Ok(())
+    }
+}
+
+impl Simulation for Base3DSimulation {
+    type Particle = Particle3;
+
+    fn step(&mut self, _dt: f32) {}
+
+    fn particles(&self) -> &[Particle3] {
+        &self.points
+    }
 }
 
 impl Model {
-    pub fn new() -> Self {
+    pub fn new(options: &Base3DOptions) -> Self {
+        let particle_color = options.particle_color.into_format();
+
         Self {
+            options: *options,
+            timestep: FixedTimestep::new(options.timestep),
             camera: Camera::new_perspective(
                 vec3(3.0, 3.0, 3.0),
                 vec3(-1.0, -1.0, -1.0).normalize(),
@@ -42,94 +169,135 @@ impl Model {
                 vec3(0.0, 0.0, 0.0),
                 1.0,
             )),
-            points: vec![
-                Particle3::new(
-                    pt3(1.0, 1.0, 1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(1.0, 1.0, -1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(1.0, -1.0, 1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(1.0, -1.0, -1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(-1.0, 1.0, 1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(-1.0, 1.0, -1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(-1.0, -1.0, 1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
-                Particle3::new(
-                    pt3(-1.0, -1.0, -1.0),
-                    Alpha {
-                        color: rgb(0.0, 0.0, 0.0),
-                        alpha: 1.0,
-                    },
-                    1.0
-                ),
+            simulation: Base3DSimulation {
+                points: vec![
+                    Particle3::new(
+                        pt3(1.0, 1.0, 1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(1.0, 1.0, -1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(1.0, -1.0, 1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(1.0, -1.0, -1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(-1.0, 1.0, 1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(-1.0, 1.0, -1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(-1.0, -1.0, 1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
+                    Particle3::new(
+                        pt3(-1.0, -1.0, -1.0),
+                        Alpha {
+                            color: particle_color,
+                            alpha: 1.0,
+                        },
+                        1.0
+                    ),
 
-            ],
+                ],
+            },
+            config: None,
+            snapshots: SnapshotFile::for_scene(Base3DScene::NAME),
         }
     }
+
+    /// Applies new options to the running scene. The window size only takes effect on the next launch.
+    pub fn reconfigure(&mut self, options: &Base3DOptions) {
+        for point in self.simulation.points.iter_mut() {
+            point.color.color = options.particle_color.into_format();
+        }
+
+        self.timestep.set_options(options.timestep);
+        self.options = *options;
+    }
 }
 
 
 #[async_trait]
 impl Scene for Base3DScene {
-    type SceneOptions = ();
+    type SceneOptions = Base3DOptions;
     type Model = Model;
 
-    fn new_scene(_options: &Self::SceneOptions) -> Self {
+    const NAME: &'static str = "base3d";
+    const DESCRIPTION: &'static str = "The corners of a cube, for trying out the 3D camera";
+
+    fn parameters() -> Vec<Parameter> {
+        Base3DOptions::parameters()
+    }
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions {
+        Base3DOptions::from_parameters(values)
+    }
+
+    fn new_scene(options: &Self::SceneOptions) -> Self {
         Self {
-            model_fn: model,
+            options: *options,
             update_fn: update,
-            view_fn: view,
+            advance_fn: advance,
+            draw_fn: draw,
             event_fn: event,
+            config: None,
+            snapshots: None,
         }
     }
 
-    async fn app(&self) -> Builder<Self::Model> {
-        let model = Model::new();
+    fn watch_config(&mut self, watcher: ConfigWatcher) {
+        self.config = Some(watcher);
+    }
+
+    fn snapshot_file(&mut self, file: SnapshotFile) {
+        self.snapshots = Some(file);
+    }
+
+    async fn app(&self) -> Result<Builder<Self::Model>, String> {
+        let mut model = Model::new(&self.options);
+        model.config = self.config.clone();
+        if let Some(snapshots) = self.snapshots.clone() {
+            snapshots.resume(&mut model)?;
+            model.snapshots = snapshots;
+        }
 
         thread_local!(static MODEL: RefCell<Option<Model>> = Default::default());
         MODEL.with(|m| m.borrow_mut().replace(model));
@@ -144,65 +312,95 @@ impl Scene for Base3DScene {
                     ..Default::default()
                 };
 
+                let model = MODEL.with(|m| m.borrow_mut().take().unwrap());
+                let window = model.options.window;
+
                 app.new_window()
                     .device_descriptor(device_descriptor)
                     .view(view)
                     .title("n0ls Base3D")
+                    .size(window.width, window.height)
                     .build_async()
                     .await
                     .unwrap();
 
-                MODEL.with(|m| m.borrow_mut().take().unwrap())
+                model
             })
         });
 
-        builder
+        Ok(builder
             .update(self.update_fn)
-            .event(self.event_fn)
+            .event(self.event_fn))
+    }
 
-        // Old code
-        // nannou::app(self.model_fn)
-        //     .update(self.update_fn)
-        //     .event(self.event_fn)
-        //     .simple_window(self.view_fn)
-        //     .size(1800, 1200)
+    fn instance(&self) -> Box<dyn SceneInstance> {
+        Box::new(ModelInstance {
+            model: Model::new(&self.options),
+            update_fn: self.update_fn,
+            advance_fn: self.advance_fn,
+            event_fn: self.event_fn,
+            draw_fn: self.draw_fn,
+            background: self.options.background(),
+        })
     }
 }
 
 
-fn model(_app: &App) -> Model {
-    Model::new()
-}
-
 fn update(app: &App, model: &mut Model, _update: Update) {
-    let win = app.window_rect();
-    let aspect_ratio = win.x.len() / win.y.len();
+    let context = RenderContext::from_app(app);
 
-    model.camera.aspect_ratio(aspect_ratio);
+    if let Some(values) = model.config.as_mut().and_then(|config| config.poll(context.frame_time)) {
+        model.reconfigure(&Base3DOptions::from_parameters(&values));
+    }
 
+    advance(model, &context);
     model.camera_controls.apply_to_camera(&mut model.camera, app);
 }
 
+fn advance(model: &mut Model, context: &RenderContext) {
+    let aspect_ratio = context.bounds.x.len() / context.bounds.y.len();
+
+    model.camera.aspect_ratio(aspect_ratio);
+    model.timestep.advance(&mut model.simulation, context.frame_time);
+}
+
 fn view(app: &App, model: &Model, frame: Frame) {
-    let win = app.window_rect();
-    let draw = app.draw();
+    let mut draw = app.draw();
+    self::draw(&RenderContext::from_app(app), model, &mut draw, 1.0);
+    draw.to_frame(app, &frame).unwrap();
+}
 
-    draw.background().color(BACKGROUND_COLOR);
+fn draw(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32) {
+    let win = context.bounds;
+
+    // Covering the window instead of clearing it, so the scene can be faded in
+    painter.rect(win, Alpha { color: model.options.background().into_format(), alpha: opacity });
     let transformation_matrix = model.camera.get_transformation_matrix();
 
-    for particle in model.points.iter() {
-        let new_particle_position = particle.transform_position(transformation_matrix);
-        let nannou_coordinate_transformation = Mat4::from_diagonal(vec4(win.x.end, win.y.end, 1.0, 1.0));
-        let new_particle_position = nannou_coordinate_transformation * new_particle_position;
-        draw.ellipse()
-            .radius(particle.radius / new_particle_position.w * 50.0)
-            .color(particle.color)
-            .xy(new_particle_position.xy() / new_particle_position.w);
-    }
+    // The corners of the cube lie this far from its middle
+    let depth_cue = DepthCue::new(&model.camera, Vec3::ZERO, Vec3::ONE.length(), model.options.depth_cue, model.options.background());
+    let points = &model.simulation.points;
 
-    draw.to_frame(app, &frame).unwrap();
+    for (i, depth) in back_to_front(&model.camera, points.iter().map(|point| point.position)) {
+        let particle = &points[i];
+        let (color, size) = depth_cue.apply(depth, particle.color, particle.radius * 50.0);
+        let clip = particle.transform_position(transformation_matrix);
+        let Some((center, radius)) = project_point(clip, size, win) else {
+            continue;
+        };
+        painter.ellipse(
+            center,
+            radius,
+            Alpha { color: color.color, alpha: color.alpha * opacity },
+        );
+    }
 }
 
 fn event(app: &App, model: &mut Model, event: Event) {
+    model.timestep.event(&event);
+
+    let snapshots = model.snapshots.clone();
+    snapshots.event(model, &event);
+
     model.camera_controls.event(app, event);
 }
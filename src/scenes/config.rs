@@ -0,0 +1,142 @@
+//! Watching a scene's config file, so parameter changes show up without relaunching the window.
+
+use std::fs;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use clap::ArgMatches;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// How often the config file's modification time is checked, in seconds.
+const POLL_INTERVAL: f32 = 0.5;
+
+/// Polls a config file and rereads the parameter values when it changes.
+/// Values given on the command line keep overriding the file, as they did on startup.
+#[derive(Clone, Debug)]
+pub struct ConfigWatcher {
+    path: PathBuf,
+    parameters: Vec<Parameter>,
+    /// Only the values that were given on the command line.
+    overrides: ParameterValues,
+    modified: Option<SystemTime>,
+    since_poll: f32,
+}
+
+impl ConfigWatcher {
+    pub fn new(path: PathBuf, parameters: Vec<Parameter>, overrides: ParameterValues) -> Self {
+        let modified = modified(&path);
+
+        Self {
+            path,
+            parameters,
+            overrides,
+            modified,
+            since_poll: 0.0,
+        }
+    }
+
+    /// Creates a watcher for the `--config` file of a scene subcommand, if one was given.
+    pub fn from_matches(parameters: &[Parameter], matches: &ArgMatches) -> Result<Option<Self>, String> {
+        let Some(path) = matches.get_one::<PathBuf>("config") else {
+            return Ok(None);
+        };
+
+        let mut overrides = ParameterValues::default();
+        overrides.apply_matches(parameters, matches)?;
+
+        Ok(Some(Self::new(path.clone(), parameters.to_vec(), overrides)))
+    }
+
+    /// Checks the file every `POLL_INTERVAL` seconds of frame time,
+    /// returning the new values when it was modified since the last read.
+    /// A file that can't be read or parsed is reported and skipped, so a half-saved edit doesn't end the scene.
+    pub fn poll(&mut self, frame_time: f32) -> Option<ParameterValues> {
+        self.since_poll += frame_time;
+        if self.since_poll < POLL_INTERVAL {
+            return None;
+        }
+        self.since_poll = 0.0;
+
+        let modified = modified(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+
+        match ParameterValues::from_config(&self.parameters, &self.path) {
+            Ok(mut values) => {
+                values.extend(&self.overrides);
+                Some(values)
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                None
+            },
+        }
+    }
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use clap::{Arg, Command, value_parser};
+    use super::*;
+
+    fn parameters() -> Vec<Parameter> {
+        vec![
+            Parameter::flag("show-vectors", "Shows the vectors"),
+            Parameter::integer("particle-count", "The amount of particles", 100)
+                .with_range(1.0, 1000.0),
+            Parameter::float("noise-scale", "The scale of the noise", 4.0),
+        ]
+    }
+
+    fn watcher(path: &Path, args: &[&str]) -> ConfigWatcher {
+        let matches = Command::new("scene")
+            .arg(Arg::new("config").long("config").value_parser(value_parser!(PathBuf)))
+            .args(parameters().iter().map(Parameter::arg))
+            .try_get_matches_from(["scene", "--config", path.to_str().unwrap()].into_iter().chain(args.iter().copied()))
+            .unwrap();
+
+        ConfigWatcher::from_matches(&parameters(), &matches).unwrap().unwrap()
+    }
+
+    #[test]
+    fn reloaded_configs_keep_the_command_line_overrides() {
+        let path = std::env::temp_dir().join(format!("config-watcher-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "particle-count": 10, "noise-scale": 2.0, "show-vectors": true }"#).unwrap();
+
+        let mut watcher = watcher(&path, &["--particle-count", "20", "--show-vectors=false"]);
+        // As if the file changed since the scene started
+        watcher.modified = None;
+        let values = watcher.poll(POLL_INTERVAL);
+        fs::remove_file(&path).unwrap();
+
+        let values = values.expect("the changed file wasn't reread");
+        assert_eq!(values.integer("particle-count"), 20);
+        assert_eq!(values.float("noise-scale"), 2.0);
+        assert!(!values.flag("show-vectors"));
+    }
+
+    #[test]
+    fn invalid_configs_are_skipped() {
+        let path = std::env::temp_dir().join(format!("config-watcher-invalid-{}.json", std::process::id()));
+        fs::write(&path, r#"{ "particle-count": 5000 }"#).unwrap();
+
+        let mut watcher = watcher(&path, &[]);
+        watcher.modified = None;
+        let values = watcher.poll(POLL_INTERVAL);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(values, None);
+    }
+}
@@ -0,0 +1,655 @@
+use std::cell::RefCell;
+
+use nannou::prelude::*;
+use nannou::{App, Frame};
+use nannou::app::Builder;
+use nannou::color::Alpha;
+use nannou::event::Update;
+use nannou::noise::Seedable;
+use rand::{Rng, SeedableRng};
+use async_trait::async_trait;
+use nannou::wgpu::{DeviceDescriptor, Limits};
+use serde_json::{json, Value};
+use crate::math_3d::Camera;
+use crate::math_3d::clip::project_point;
+use crate::math_3d::controls::{CameraControls, CenteredCameraControls};
+use crate::math_3d::depth::{back_to_front, DepthCue, DepthCueOptions};
+use crate::colormap::{age_fraction, ColorSample, ColormapOptions};
+use crate::integrator::IntegratorOptions;
+use crate::lifecycle::{BoundaryPolicy, LifecycleOptions};
+use crate::noise_field::{curl_3d, NoiseField, NoiseOptions};
+use crate::particle::Particle3;
+use crate::plot::Plottable;
+use crate::render::{Painter, RenderContext};
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::{ModelInstance, Scene, SceneInstance, WindowOptions};
+use crate::simulation::{SceneRng, seeded_rng, Simulation};
+use crate::snapshot::{check_settings, f64_field, field, restore_all, restored, settings_json, Snapshot, snapshot_all, SnapshotFile, u32_field};
+use crate::timestep::{FixedTimestep, TimestepOptions};
+
+
+/// Caps the speed at this multiple of the flow speed.
+/// Worley noise is constant within its cells, so its curl is zero inside them and in the thousands across their edges,
+/// which would fling a particle out of the box in a single step. The curl of the smooth kinds stays below 40 at their defaults.
+const MAX_SPEED_FACTOR: f32 = 50.0;
+
+/// About the highest speed in the flows of Perlin noise and its plain fractals as a multiple of the flow speed,
+/// the end of the gradient when coloring by speed. The rougher fractals reach past it, where the particles take its color.
+const COLORMAP_SPEED_FACTOR: f32 = 4.0;
+
+pub struct CurlFlowScene {
+    options: CurlFlowOptions,
+    update_fn: fn(app: &App, model: &mut Model, _update: Update),
+    advance_fn: fn(model: &mut Model, context: &RenderContext),
+    draw_fn: fn(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32),
+    event_fn: fn(app: &App, model: &mut Model, event: Event),
+    config: Option<ConfigWatcher>,
+    snapshots: Option<SnapshotFile>,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CurlFlowOptions {
+    /// The seed for the scene's RNG, a random one is picked when `None`.
+    pub seed: Option<u32>,
+    pub particle_count: usize,
+    pub particle_radius: f32,
+    pub particle_color: Srgb<u8>,
+    /// Colors the particles by an attribute like their angle or speed, instead of the particle color.
+    pub colormap: ColormapOptions,
+    pub particle_alpha: f32,
+    /// The particles move within a cube reaching this far from the origin along every axis.
+    pub extent: f32,
+    /// Particles spawn at most this far from the origin along every axis.
+    pub spawn_extent: f32,
+    /// How long particles live and what happens when they leave the cube.
+    pub lifecycle: LifecycleOptions,
+    /// How many noise features fit along an edge of the cube.
+    pub noise_scale: f64,
+    /// The noise function the potential of the flow is built from.
+    pub noise: NoiseOptions,
+    /// How fast the particles move with the flow, in world units per second.
+    pub flow_speed: f32,
+    /// How fast the flow changes over time, it is static at 0.
+    pub evolution_speed: f64,
+    /// How the particles follow the flow.
+    pub integrator: IntegratorOptions,
+    /// The palette's background is used when it isn't set.
+    pub background_color: Option<Srgb<u8>>,
+    /// The alpha of the background laid over every frame, lower values give longer trails.
+    pub trail_fade: f32,
+    /// Fades and shrinks the particles further from the camera.
+    pub depth_cue: DepthCueOptions,
+    pub window: WindowOptions,
+    pub timestep: TimestepOptions,
+}
+
+impl Default for CurlFlowOptions {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            particle_count: 2000,
+            particle_radius: 4.0,
+            particle_color: BLACK,
+            colormap: ColormapOptions::with_palette("parchment"),
+            particle_alpha: 0.8,
+            extent: 50.0,
+            spawn_extent: 50.0,
+            lifecycle: LifecycleOptions {
+                boundary: BoundaryPolicy::Wrap,
+                ..LifecycleOptions::default()
+            },
+            noise_scale: 2.0,
+            noise: NoiseOptions::default(),
+            flow_speed: 20.0,
+            evolution_speed: 0.05,
+            integrator: IntegratorOptions::default(),
+            background_color: None,
+            trail_fade: 0.05,
+            depth_cue: DepthCueOptions::default(),
+            window: WindowOptions {
+                width: 1024,
+                height: 768,
+            },
+            timestep: TimestepOptions::default(),
+        }
+    }
+}
+
+impl CurlFlowOptions {
+    pub fn parameters() -> Vec<Parameter> {
+        let defaults = Self::default();
+        let mut parameters = vec![
+            Parameter::seed(),
+            Parameter::integer("particle-count", "The amount of particles", defaults.particle_count as i64)
+                .with_range(1.0, 1_000_000.0),
+            Parameter::float("particle-radius", "The radius of the particles", defaults.particle_radius)
+                .with_range(0.0, 1000.0),
+            Parameter::color("particle-color", "The color of the particles", defaults.particle_color),
+            Parameter::float("particle-alpha", "The alpha of the particles", defaults.particle_alpha)
+                .with_range(0.0, 1.0),
+            Parameter::float("extent", "The particles move within a cube reaching this far from the origin along every axis", defaults.extent)
+                .with_range(1.0, 10_000.0),
+            Parameter::float("spawn-extent", "Particles spawn at most this far from the origin along every axis", defaults.spawn_extent)
+                .with_range(0.0, 10_000.0),
+            Parameter::float("noise-scale", "How many noise features fit along an edge of the cube", defaults.noise_scale as f32)
+                .with_range(0.001, 1000.0),
+            Parameter::float("flow-speed", "How fast the particles move with the flow", defaults.flow_speed)
+                .with_range(0.0, 10_000.0),
+            Parameter::float("evolution-speed", "How fast the flow changes over time, 0 keeps it static", defaults.evolution_speed as f32)
+                .with_range(0.0, 100.0),
+            Parameter::optional_color("background-color", "The color of the background, the palette's background if not given"),
+            Parameter::float("trail-fade", "The alpha of the background laid over every frame, lower values give longer trails", defaults.trail_fade)
+                .with_range(0.0, 1.0),
+        ];
+        parameters.extend(ColormapOptions::parameters(defaults.colormap));
+        parameters.extend(LifecycleOptions::parameters(defaults.lifecycle));
+        parameters.extend(NoiseOptions::parameters(defaults.noise));
+        parameters.extend(IntegratorOptions::parameters(defaults.integrator));
+        parameters.extend(DepthCueOptions::parameters(defaults.depth_cue));
+        parameters.extend(WindowOptions::parameters(defaults.window));
+        parameters.extend(TimestepOptions::parameters(defaults.timestep));
+
+        parameters
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            seed: values.seed(),
+            particle_count: values.integer("particle-count") as usize,
+            particle_radius: values.float("particle-radius"),
+            particle_color: values.color("particle-color"),
+            colormap: ColormapOptions::from_parameters(values),
+            particle_alpha: values.float("particle-alpha"),
+            extent: values.float("extent"),
+            spawn_extent: values.float("spawn-extent"),
+            lifecycle: LifecycleOptions::from_parameters(values),
+            noise_scale: values.float("noise-scale") as f64,
+            noise: NoiseOptions::from_parameters(values),
+            flow_speed: values.float("flow-speed"),
+            evolution_speed: values.float("evolution-speed") as f64,
+            integrator: IntegratorOptions::from_parameters(values),
+            background_color: values.optional_color("background-color"),
+            trail_fade: values.float("trail-fade"),
+            depth_cue: DepthCueOptions::from_parameters(values),
+            window: WindowOptions::from_parameters(values),
+            timestep: TimestepOptions::from_parameters(values),
+        }
+    }
+
+    /// Whether going from `self` to `other` needs the particles to be respawned,
+    /// the other options can be applied to the running scene.
+    pub fn needs_reset(&self, other: &CurlFlowOptions) -> bool {
+        self.seed != other.seed
+            || self.particle_count != other.particle_count
+            || self.extent != other.extent
+            || self.spawn_extent != other.spawn_extent
+    }
+
+    /// The background color, which comes from the palette unless it is set.
+    pub fn background(&self) -> Srgb<u8> {
+        self.background_color.unwrap_or(self.colormap.palette.background)
+    }
+}
+
+
+#[async_trait]
+impl Scene for CurlFlowScene {
+    type SceneOptions = CurlFlowOptions;
+    type Model = Model;
+
+    const NAME: &'static str = "curl-flow";
+    const DESCRIPTION: &'static str = "Particles drifting through the curl of 3D noise, a flow without sinks";
+
+    fn parameters() -> Vec<Parameter> {
+        CurlFlowOptions::parameters()
+    }
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions {
+        CurlFlowOptions::from_parameters(values)
+    }
+
+    fn new_scene(options: &Self::SceneOptions) -> Self {
+        Self {
+            options: *options,
+            update_fn: update,
+            advance_fn: advance,
+            draw_fn: draw,
+            event_fn: event,
+            config: None,
+            snapshots: None,
+        }
+    }
+
+    fn watch_config(&mut self, watcher: ConfigWatcher) {
+        self.config = Some(watcher);
+    }
+
+    fn snapshot_file(&mut self, file: SnapshotFile) {
+        self.snapshots = Some(file);
+    }
+
+    async fn app(&self) -> Result<Builder<Self::Model>, String> {
+        let mut model = Model::new(&self.options);
+        model.config = self.config.clone();
+        if let Some(snapshots) = self.snapshots.clone() {
+            snapshots.resume(&mut model)?;
+            model.snapshots = snapshots;
+        }
+
+        thread_local!(static MODEL: RefCell<Option<Model>> = Default::default());
+        MODEL.with(|m| m.borrow_mut().replace(model));
+
+        let builder = app::Builder::new_async(|app| {
+            Box::new(async move {
+                let device_descriptor = DeviceDescriptor {
+                    limits: Limits {
+                        max_texture_dimension_2d: 8192,
+                        ..Limits::downlevel_webgl2_defaults()
+                    },
+                    ..Default::default()
+                };
+
+                let model = MODEL.with(|m| m.borrow_mut().take().unwrap());
+                let window = model.options.window;
+
+                app.new_window()
+                    .device_descriptor(device_descriptor)
+                    .view(view)
+                    .title("n0ls Curl Flow")
+                    .size(window.width, window.height)
+                    .build_async()
+                    .await
+                    .unwrap();
+
+                model
+            })
+        });
+
+        Ok(builder
+            .update(self.update_fn)
+            .event(self.event_fn))
+    }
+
+    fn instance(&self) -> Box<dyn SceneInstance> {
+        Box::new(ModelInstance {
+            model: Model::new(&self.options),
+            update_fn: self.update_fn,
+            advance_fn: self.advance_fn,
+            event_fn: self.event_fn,
+            draw_fn: self.draw_fn,
+            background: self.options.background(),
+        })
+    }
+}
+
+
+pub struct Model {
+    pub options: CurlFlowOptions,
+    /// The seed the scene's RNG was created with.
+    pub seed: u32,
+    pub simulation: CurlFlowSimulation,
+    pub timestep: FixedTimestep,
+    pub camera: Camera,
+    pub camera_controls: Box<dyn CameraControls>,
+    /// The config file whose changes are applied while running, if any.
+    pub config: Option<ConfigWatcher>,
+    pub snapshots: SnapshotFile,
+}
+
+impl Model {
+    pub fn new(options: &CurlFlowOptions) -> Self {
+        let view_center = vec3(0.0, 0.0, 0.0);
+        let cam_position = vec3(1.0, 0.6, 1.0).normalize() * options.extent * 4.0;
+        let view_direction = (view_center - cam_position).normalize();
+        let up = vec3(0.0, 1.0, 0.0);
+        let right = view_direction.cross(up).normalize();
+        let up = right.cross(view_direction).normalize(); // calculate the real up vector
+
+        let (seed, mut rng) = seeded_rng(options.seed);
+
+        Model {
+            options: *options,
+            seed,
+            simulation: CurlFlowSimulation::new(&mut rng, options),
+            timestep: FixedTimestep::new(options.timestep),
+            camera: Camera::new_perspective(
+                cam_position,
+                view_direction,
+                up,
+                0.25 * PI,
+                1.0,
+            ),
+            camera_controls: Box::new(CenteredCameraControls::new(
+                view_center,
+                options.extent * 2.0,
+                options.extent * 2.0,
+            )),
+            config: None,
+            snapshots: SnapshotFile::for_scene(CurlFlowScene::NAME),
+        }
+    }
+
+    /// Applies new options to the running scene.
+    /// The particles are only respawned when a structural option like the seed or particle count changes,
+    /// the rest is applied in place. The window size only takes effect on the next launch.
+    pub fn reconfigure(&mut self, options: &CurlFlowOptions) {
+        if self.options.needs_reset(options) {
+            let (seed, mut rng) = seeded_rng(options.seed);
+            self.seed = seed;
+            self.simulation = CurlFlowSimulation::new(&mut rng, options);
+        } else {
+            if self.options.noise != options.noise {
+                self.simulation.noise_fn = NoiseField::new(&options.noise).set_seed(self.simulation.seed);
+            }
+            self.simulation.noise_scale = options.noise_scale;
+            self.simulation.flow_speed = options.flow_speed;
+            self.simulation.evolution_speed = options.evolution_speed;
+            self.simulation.integrator = options.integrator;
+            if self.options.lifecycle != options.lifecycle {
+                self.simulation.lifecycle = options.lifecycle;
+                self.simulation.assign_lifetimes();
+            }
+            for particle in self.simulation.particles.iter_mut() {
+                particle.color = Alpha {
+                    color: options.particle_color.into_format(),
+                    alpha: options.particle_alpha,
+                };
+                particle.radius = options.particle_radius;
+            }
+        }
+
+        self.timestep.set_options(options.timestep);
+        self.options = *options;
+    }
+}
+
+impl Plottable for Model {}
+
+/// The options aren't restored, a snapshot is loaded into a scene that was started with them.
+/// The ones that change how the particles move are written as settings, and a snapshot taken with others is rejected.
+impl Snapshot for Model {
+    fn snapshot(&self) -> Value {
+        json!({
+            "settings": self.settings(),
+            "seed": self.seed,
+            "timestep": self.timestep.snapshot(),
+            "camera": self.camera.snapshot(),
+            "simulation": self.simulation.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        check_settings(snapshot, &self.settings())?;
+        let seed = u32_field(snapshot, "seed")?;
+        let timestep = restored(&self.timestep, snapshot, "timestep")?;
+        let camera = Camera::from_snapshot(field(snapshot, "camera")?)?;
+        // Restored last, since it's left as it was when its own part is invalid
+        self.simulation.restore(field(snapshot, "simulation")?)?;
+
+        self.seed = seed;
+        self.timestep = timestep;
+        self.camera = camera;
+
+        Ok(())
+    }
+}
+
+impl Model {
+    fn settings(&self) -> Value {
+        let options = &self.options;
+        settings_json([
+            json!({
+                "extent": options.extent,
+                "spawn-extent": options.spawn_extent,
+                "noise-scale": options.noise_scale,
+                "flow-speed": options.flow_speed,
+                "evolution-speed": options.evolution_speed,
+            }),
+            options.noise.settings(),
+            options.integrator.settings(),
+            options.lifecycle.settings(),
+            options.timestep.settings(),
+        ])
+    }
+}
+
+/// The window-free part of the curl flow scene.
+pub struct CurlFlowSimulation {
+    pub particles: Vec<Particle3>,
+    pub seed: u32,
+    pub noise_fn: NoiseField,
+    pub noise_scale: f64,
+    pub extent: f32,
+    /// Particles spawn at most this far from the origin, which is where they respawn at the emitter.
+    pub spawn_extent: f32,
+    pub lifecycle: LifecycleOptions,
+    /// Picks the lifetimes and the places particles respawn at.
+    pub rng: SceneRng,
+    pub flow_speed: f32,
+    pub evolution_speed: f64,
+    pub integrator: IntegratorOptions,
+    /// The simulated seconds since the start, which the flow evolves with.
+    pub time: f64,
+}
+
+impl CurlFlowSimulation {
+    pub fn new<R: Rng>(rng: &mut R, options: &CurlFlowOptions) -> Self {
+        let seed: u32 = rng.gen();
+        let spawn_extent = options.spawn_extent.min(options.extent);
+
+        let particles = vec![0; options.particle_count].into_iter().map(|_| {
+            Particle3::new(
+                pt3(
+                    rng.gen_range(-spawn_extent..=spawn_extent),
+                    rng.gen_range(-spawn_extent..=spawn_extent),
+                    rng.gen_range(-spawn_extent..=spawn_extent),
+                ),
+                Alpha {
+                    color: options.particle_color.into_format(),
+                    alpha: options.particle_alpha,
+                },
+                options.particle_radius,
+            )
+        }).collect::<Vec<_>>();
+
+        let mut simulation = Self {
+            particles,
+            seed,
+            noise_fn: NoiseField::new(&options.noise).set_seed(seed),
+            noise_scale: options.noise_scale,
+            extent: options.extent,
+            spawn_extent,
+            lifecycle: options.lifecycle,
+            rng: SceneRng::seed_from_u64(rng.gen()),
+            flow_speed: options.flow_speed,
+            evolution_speed: options.evolution_speed,
+            integrator: options.integrator,
+            time: 0.0,
+        };
+
+        simulation.assign_lifetimes();
+        simulation
+    }
+
+    /// Gives every particle a new lifetime, at a random point of which it currently is,
+    /// so the particles don't all respawn at once.
+    pub fn assign_lifetimes(&mut self) {
+        for particle in self.particles.iter_mut() {
+            particle.lifetime = self.lifecycle.random_lifetime(&mut self.rng);
+            if let Some(lifetime) = particle.lifetime {
+                particle.age = self.rng.gen_range(0.0..=lifetime);
+            }
+        }
+    }
+
+    /// Where a particle respawns, at the emitter in the middle or anywhere in the cube depending on the boundary policy.
+    fn respawn_position(&mut self) -> Point3 {
+        let extent = match self.lifecycle.boundary {
+            BoundaryPolicy::RespawnEmitter => self.spawn_extent,
+            _ => self.extent,
+        };
+
+        pt3(
+            self.rng.gen_range(-extent..=extent),
+            self.rng.gen_range(-extent..=extent),
+            self.rng.gen_range(-extent..=extent),
+        )
+    }
+
+    /// Colors the particles by the colormap's attribute, unless they keep the particle color.
+    /// Distances are relative to the corners of the cube, heights to its bottom and top.
+    pub fn apply_colormap(&mut self, colormap: &ColormapOptions) {
+        let corner_distance = Vec3::splat(self.extent).length();
+        let colormap_speed = (COLORMAP_SPEED_FACTOR * self.flow_speed).max(f32::EPSILON);
+
+        for particle in self.particles.iter_mut() {
+            let sample = ColorSample {
+                angle: particle.velocity.y.atan2(particle.velocity.x),
+                speed: (particle.velocity.length() / colormap_speed).min(1.0),
+                age: age_fraction(particle.age, particle.lifetime),
+                distance: particle.position.length() / corner_distance,
+                height: (particle.position.z + self.extent) / (2.0 * self.extent),
+            };
+
+            if let Some(color) = colormap.color(&sample) {
+                particle.color.color = color;
+            }
+        }
+    }
+
+    /// The velocity of the flow at the position.
+    pub fn velocity_at(&self, position: Point3) -> Vec3 {
+        let noise_coords = position.as_f64() / (2.0 * self.extent as f64) * self.noise_scale;
+        let [x, y, z] = curl_3d(&self.noise_fn, noise_coords.to_array(), self.time * self.evolution_speed);
+
+        let velocity = vec3(x as f32, y as f32, z as f32) * self.flow_speed;
+        velocity.clamp_length_max(self.flow_speed * MAX_SPEED_FACTOR)
+    }
+}
+
+impl Snapshot for CurlFlowSimulation {
+    fn snapshot(&self) -> Value {
+        json!({
+            "noise_seed": self.seed,
+            "time": self.time,
+            "particles": snapshot_all(&self.particles),
+            "rng": self.rng.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let seed = u32_field(snapshot, "noise_seed")?;
+        let time = f64_field(snapshot, "time")?;
+        let particles = restore_all(snapshot, "particles")?;
+        let rng = restored(&self.rng, snapshot, "rng")?;
+
+        self.seed = seed;
+        self.noise_fn = self.noise_fn.clone().set_seed(seed);
+        self.time = time;
+        self.particles = particles;
+        self.rng = rng;
+
+        Ok(())
+    }
+}
+
+impl Simulation for CurlFlowSimulation {
+    type Particle = Particle3;
+
+    /// Moves the particles along with the flow, without inertia, since that would let them clump together again.
+    fn step(&mut self, dt: f32) {
+        let positions = self.particles.iter()
+            .map(|particle| self.integrator.step(particle.position, dt, |position| self.velocity_at(position)))
+            .collect::<Vec<_>>();
+        let corner = Vec3::splat(self.extent);
+
+        for (i, position) in positions.into_iter().enumerate() {
+            let particle = &mut self.particles[i];
+            // The average velocity over the step, which is the flow's velocity at the start for Euler
+            particle.velocity = (position - particle.position) / dt;
+            particle.position = position;
+            particle.age += dt;
+
+            if !particle.confine(-corner, corner, self.lifecycle.boundary) || particle.is_expired() {
+                let position = self.respawn_position();
+                let lifetime = self.lifecycle.random_lifetime(&mut self.rng);
+                self.particles[i].respawn(position, lifetime);
+            }
+        }
+
+        self.time += dt as f64;
+    }
+
+    fn particles(&self) -> &[Particle3] {
+        &self.particles
+    }
+}
+
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let mut draw = app.draw();
+
+    if app.elapsed_frames() < 2 {
+        draw.background().color(model.options.background());
+    }
+
+    self::draw(&RenderContext::from_app(app), model, &mut draw, 1.0);
+    draw.to_frame(app, &frame).unwrap();
+}
+
+fn draw(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32) {
+    let win = context.bounds;
+
+    painter.rect(win, Alpha { color: model.options.background().into_format(), alpha: model.options.trail_fade * opacity });
+
+    let transformation_matrix = model.camera.get_transformation_matrix();
+    let reach = Vec3::splat(model.options.extent).length();
+    let depth_cue = DepthCue::new(&model.camera, Vec3::ZERO, reach, model.options.depth_cue, model.options.background());
+    let particles = &model.simulation.particles;
+
+    for (i, depth) in back_to_front(&model.camera, particles.iter().map(|particle| particle.position)) {
+        let particle = &particles[i];
+        let (color, size) = depth_cue.apply(depth, particle.color, particle.radius * 50.0);
+        let clip = particle.transform_position(transformation_matrix);
+        let Some((center, radius)) = project_point(clip, size, win) else {
+            continue;
+        };
+        painter.ellipse(
+            center,
+            radius,
+            Alpha { color: color.color, alpha: color.alpha * particle.fade(model.options.lifecycle.fade_duration) * opacity },
+        );
+    }
+}
+
+fn update(app: &App, model: &mut Model, _update: Update) {
+    let context = RenderContext::from_app(app);
+
+    if let Some(values) = model.config.as_mut().and_then(|config| config.poll(context.frame_time)) {
+        model.reconfigure(&CurlFlowOptions::from_parameters(&values));
+    }
+
+    advance(model, &context);
+    model.camera_controls.apply_to_camera(&mut model.camera, app);
+}
+
+fn advance(model: &mut Model, context: &RenderContext) {
+    let aspect_ratio = context.bounds.x.len() / context.bounds.y.len();
+
+    model.camera.aspect_ratio(aspect_ratio);
+    model.timestep.advance(&mut model.simulation, context.frame_time);
+    model.simulation.apply_colormap(&model.options.colormap);
+
+    // The particles never leave the cube, its corners are the furthest they get from the middle
+    model.camera.fit_far_plane(Vec3::ZERO, Vec3::splat(model.options.extent).length());
+}
+
+fn event(app: &App, model: &mut Model, event: Event) {
+    model.timestep.event(&event);
+
+    let snapshots = model.snapshots.clone();
+    snapshots.event(model, &event);
+
+    model.camera_controls.event(app, event);
+}
@@ -0,0 +1,227 @@
+//! A host app that owns a single window and can switch between all registered scenes.
+//!
+//! Keys:
+//! - `1` to `9` switch to the scene at that position in the registry
+//! - `Tab` switches to the next scene
+
+use std::cell::RefCell;
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use nannou::prelude::*;
+use nannou::app::Builder;
+use nannou::color::Alpha;
+use nannou::wgpu::{DeviceDescriptor, Limits};
+use crate::render::RenderContext;
+use crate::scenes::parameters::ParameterValues;
+use crate::scenes::registry::SceneRegistry;
+use crate::scenes::{SceneInstance, WindowOptions};
+
+
+/// How much of the window the incoming scene's background covers per second of a crossfade,
+/// spread over the frames so the fade doesn't depend on the frame rate.
+const CROSSFADE_BACKGROUND_COVERAGE_PER_SECOND: f32 = 0.95;
+
+#[derive(Clone, Debug)]
+pub struct HostOptions {
+    /// The name of the scene to start with.
+    pub scene: String,
+    /// The duration of the crossfade between scenes in seconds, `0` switches instantly.
+    pub crossfade: f32,
+}
+
+impl HostOptions {
+    pub fn command() -> Command {
+        Command::new("host")
+            .about("Runs the scenes in a single window, switching between them with the number keys and Tab")
+            .arg(Arg::new("scene")
+                .long("scene")
+                .help("The scene to start with")
+                .default_value("perlin-flow"))
+            .arg(Arg::new("crossfade")
+                .long("crossfade")
+                .help("The duration of the crossfade between scenes in seconds, 0 switches instantly")
+                .value_parser(value_parser!(f32))
+                .default_value("1.0"))
+    }
+
+    pub fn from_matches(matches: &ArgMatches) -> Self {
+        Self {
+            scene: matches.get_one::<String>("scene").unwrap().clone(),
+            crossfade: *matches.get_one::<f32>("crossfade").unwrap(),
+        }
+    }
+}
+
+
+pub struct HostScene {
+    options: HostOptions,
+}
+
+impl HostScene {
+    pub fn new(options: HostOptions) -> Self {
+        Self {
+            options,
+        }
+    }
+
+    pub async fn app(&self) -> Result<Builder<Model>, String> {
+        let model = Model::new(SceneRegistry::default(), &self.options)?;
+
+        thread_local!(static MODEL: RefCell<Option<Model>> = Default::default());
+        MODEL.with(|m| m.borrow_mut().replace(model));
+
+        let builder = app::Builder::new_async(|app| {
+            Box::new(async move {
+                let device_descriptor = DeviceDescriptor {
+                    limits: Limits {
+                        max_texture_dimension_2d: 8192,
+                        ..Limits::downlevel_webgl2_defaults()
+                    },
+                    ..Default::default()
+                };
+
+                let model = MODEL.with(|m| m.borrow_mut().take().unwrap());
+                let window = model.window;
+
+                app.new_window()
+                    .device_descriptor(device_descriptor)
+                    .view(view)
+                    .title("n0ls Generative Art")
+                    .size(window.width, window.height)
+                    .build_async()
+                    .await
+                    .unwrap();
+
+                model
+            })
+        });
+
+        Ok(builder
+            .update(update)
+            .event(event))
+    }
+}
+
+
+/// The scene being faded out.
+struct Transition {
+    outgoing: Box<dyn SceneInstance>,
+    elapsed: f32,
+}
+
+pub struct Model {
+    registry: SceneRegistry,
+    active_index: usize,
+    active: Box<dyn SceneInstance>,
+    transition: Option<Transition>,
+    crossfade: f32,
+    /// The size of the starting scene's window.
+    window: WindowOptions,
+    /// The window gets cleared up to this frame, after switching without a crossfade.
+    clear_until_frame: u64,
+}
+
+impl Model {
+    pub fn new(registry: SceneRegistry, options: &HostOptions) -> Result<Self, String> {
+        let active_index = registry.position(&options.scene)
+            .ok_or_else(|| format!("Unknown scene `{}`", options.scene))?;
+        let entry = &registry.entries()[active_index];
+        let values = ParameterValues::defaults(&entry.parameters);
+        let active = entry.instance(&values);
+
+        Ok(Self {
+            registry,
+            active_index,
+            active,
+            transition: None,
+            crossfade: options.crossfade,
+            window: WindowOptions::from_parameters(&values),
+            clear_until_frame: 2,
+        })
+    }
+
+    /// Switches to the scene at the given position in the registry, using its default parameters.
+    pub fn switch_to(&mut self, app: &App, index: usize) {
+        let Some(entry) = self.registry.entries().get(index) else {
+            return;
+        };
+
+        let incoming = entry.instance(&ParameterValues::defaults(&entry.parameters));
+        let outgoing = std::mem::replace(&mut self.active, incoming);
+        self.active_index = index;
+
+        if self.crossfade > 0.0 {
+            self.transition = Some(Transition {
+                outgoing,
+                elapsed: 0.0,
+            });
+        } else {
+            self.transition = None;
+            self.clear_until_frame = app.elapsed_frames() + 2;
+        }
+    }
+
+    pub fn switch_to_next(&mut self, app: &App) {
+        let index = (self.active_index + 1) % self.registry.entries().len();
+        self.switch_to(app, index);
+    }
+}
+
+
+fn update(app: &App, model: &mut Model, update: Update) {
+    model.active.update(app, update);
+
+    if let Some(transition) = model.transition.as_mut() {
+        transition.outgoing.update(app, update);
+        transition.elapsed += update.since_last.as_secs_f32();
+
+        if transition.elapsed >= model.crossfade {
+            model.transition = None;
+        }
+    }
+}
+
+fn view(app: &App, model: &Model, frame: Frame) {
+    let win = app.window_rect();
+    let mut draw = app.draw();
+    let context = RenderContext::from_app(app);
+
+    if app.elapsed_frames() < model.clear_until_frame {
+        draw.background().color(model.active.background());
+    }
+
+    match model.transition.as_ref() {
+        Some(transition) => {
+            let progress = (transition.elapsed / model.crossfade).clamp(0.0, 1.0);
+            let alpha = 1.0 - (1.0 - CROSSFADE_BACKGROUND_COVERAGE_PER_SECOND).powf(context.frame_time);
+
+            draw.rect().wh(win.wh()).xy(win.xy()).color(Alpha { color: model.active.background(), alpha });
+            transition.outgoing.draw(&context, &mut draw, 1.0 - progress);
+            model.active.draw(&context, &mut draw, progress);
+        },
+        None => model.active.draw(&context, &mut draw, 1.0),
+    }
+
+    draw.to_frame(app, &frame).unwrap();
+}
+
+fn event(app: &App, model: &mut Model, event: Event) {
+    // The switch keys belong to the host, so the scene switched to doesn't act on them too
+    if let Event::WindowEvent { simple: Some(KeyPressed(key)), .. } = &event {
+        match key {
+            Key::Tab => return model.switch_to_next(app),
+            Key::Key1 => return model.switch_to(app, 0),
+            Key::Key2 => return model.switch_to(app, 1),
+            Key::Key3 => return model.switch_to(app, 2),
+            Key::Key4 => return model.switch_to(app, 3),
+            Key::Key5 => return model.switch_to(app, 4),
+            Key::Key6 => return model.switch_to(app, 5),
+            Key::Key7 => return model.switch_to(app, 6),
+            Key::Key8 => return model.switch_to(app, 7),
+            Key::Key9 => return model.switch_to(app, 8),
+            _ => {}
+        }
+    }
+
+    model.active.event(app, event);
+}
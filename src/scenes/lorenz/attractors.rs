@@ -0,0 +1,476 @@
+//! Strange attractors, systems of three differential equations whose solutions settle on a fractal shape.
+//! The Lorenz scene follows particles along any of them.
+
+use nannou::prelude::*;
+
+
+/// The most coefficients an attractor takes.
+pub const MAX_COEFFICIENTS: usize = 6;
+
+/// The coefficients of an attractor in the order of `Attractor::coefficients`, the ones it doesn't take are 0.
+pub type Coefficients = [f32; MAX_COEFFICIENTS];
+
+/// How many times the camera's distance to the attractor is its reach, which fits it in the view.
+const CAMERA_DISTANCE: f32 = 12.0;
+
+/// The least reach of the Lorenz attractor, whose reach follows rho,
+/// so a small or negative rho doesn't make every particle count as escaped.
+const MIN_LORENZ_REACH: f32 = 10.0;
+
+
+/// Where the camera looks at an attractor from.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct CameraView {
+    pub position: Point3,
+    pub target: Point3,
+}
+
+impl CameraView {
+    /// Looks at the target from the direction, far enough away to see everything within `reach` of it.
+    fn around(target: Point3, direction: Vec3, reach: f32) -> Self {
+        Self {
+            position: target + direction.normalize() * reach * CAMERA_DISTANCE,
+            target,
+        }
+    }
+
+    pub fn distance(&self) -> f32 {
+        self.position.distance(self.target)
+    }
+}
+
+
+/// A system of differential equations giving the velocity of a point in space, with named coefficients.
+pub trait Attractor {
+    /// The names of the coefficients with their usual values, in the order `derivative` takes them.
+    fn coefficients(&self) -> &'static [(&'static str, f32)];
+
+    /// The velocity of a point at the position.
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3;
+
+    /// The center of the box particles spawn in by default, and how far it extends from there along every axis.
+    fn initial_region(&self) -> (Point3, f32);
+
+    /// The middle of the attractor and about how far it reaches from there.
+    fn bounds(&self, coefficients: &Coefficients) -> (Point3, f32);
+
+    /// Where the camera starts out, to see the whole attractor.
+    fn camera(&self) -> CameraView {
+        let (center, reach) = self.bounds(&default_coefficients(self));
+        CameraView::around(center, vec3(0.3, 0.4, 1.0), reach)
+    }
+
+    /// How many times faster than the Lorenz attractor this one is simulated, so its particles circle it at a similar pace.
+    fn time_scale(&self) -> f32 {
+        1.0
+    }
+}
+
+/// The usual values of the attractor's coefficients.
+pub fn default_coefficients<A: Attractor + ?Sized>(attractor: &A) -> Coefficients {
+    let mut coefficients = [0.0; MAX_COEFFICIENTS];
+    for (coefficient, (_, default)) in coefficients.iter_mut().zip(attractor.coefficients()) {
+        *coefficient = *default;
+    }
+
+    coefficients
+}
+
+
+/// https://en.wikipedia.org/wiki/Lorenz_system
+pub struct Lorenz;
+
+impl Attractor for Lorenz {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("sigma", 10.0), ("rho", 28.0), ("beta", 2.66667)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [sigma, rho, beta, ..] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            sigma * (y - x),
+            x * (rho - z) - y,
+            x * y - beta * z,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 10.0)
+    }
+
+    /// The wings reach about rho from their middle, which lies at a height of rho.
+    /// Below about 25 they spiral into two points instead, and below 1 into the origin.
+    fn bounds(&self, coefficients: &Coefficients) -> (Point3, f32) {
+        let rho = coefficients[1];
+        (vec3(0.0, 0.0, rho), rho.abs().max(MIN_LORENZ_REACH))
+    }
+
+    fn camera(&self) -> CameraView {
+        CameraView {
+            position: pt3(10.926121, 7.850386, 232.43593),
+            target: Vec3::ZERO,
+        }
+    }
+}
+
+
+/// https://en.wikipedia.org/wiki/R%C3%B6ssler_attractor
+pub struct Rossler;
+
+impl Attractor for Rossler {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 0.2), ("b", 0.2), ("c", 5.7)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, c, ..] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            -y - z,
+            x + a * y,
+            b + z * (x - c),
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 5.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(1.0, -1.5, 2.0), 12.0)
+    }
+
+    fn time_scale(&self) -> f32 {
+        5.0
+    }
+}
+
+
+/// Thomas' cyclically symmetric attractor, https://en.wikipedia.org/wiki/Thomas%27_cyclically_symmetric_attractor
+pub struct Thomas;
+
+impl Attractor for Thomas {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("b", 0.208186)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let b = coefficients[0];
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            y.sin() - b * x,
+            z.sin() - b * y,
+            x.sin() - b * z,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 3.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (Vec3::ZERO, 4.0)
+    }
+
+    fn camera(&self) -> CameraView {
+        CameraView::around(Vec3::ZERO, vec3(1.0, 0.8, 0.6), 4.5)
+    }
+
+    fn time_scale(&self) -> f32 {
+        20.0
+    }
+}
+
+
+pub struct Aizawa;
+
+impl Attractor for Aizawa {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 0.95), ("b", 0.7), ("c", 0.6), ("d", 3.5), ("e", 0.25), ("f", 0.1)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, c, d, e, f] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            (z - b) * x - d * y,
+            d * x + (z - b) * y,
+            c + a * z - z.powi(3) / 3.0 - (x * x + y * y) * (1.0 + e * z) + f * z * x.powi(3),
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (vec3(0.0, 0.0, 0.5), 0.5)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(0.0, 0.0, 0.75), 1.6)
+    }
+
+    fn camera(&self) -> CameraView {
+        CameraView::around(vec3(0.0, 0.0, 0.75), vec3(0.0, -1.0, 0.45), 1.6)
+    }
+
+    fn time_scale(&self) -> f32 {
+        3.0
+    }
+}
+
+
+pub struct Halvorsen;
+
+impl Attractor for Halvorsen {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 1.89)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let a = coefficients[0];
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            -a * x - 4.0 * y - 4.0 * z - y * y,
+            -a * y - 4.0 * z - 4.0 * x - z * z,
+            -a * z - 4.0 * x - 4.0 * y - x * x,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (vec3(-1.0, 0.0, 0.0), 2.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(-3.0, -3.0, -3.0), 10.0)
+    }
+
+    /// Looking along the diagonal shows its threefold symmetry.
+    fn camera(&self) -> CameraView {
+        CameraView::around(vec3(-3.0, -3.0, -3.0), vec3(1.0, 1.0, 1.0), 10.0)
+    }
+
+    fn time_scale(&self) -> f32 {
+        1.5
+    }
+}
+
+
+/// https://en.wikipedia.org/wiki/Multiscroll_attractor#Chen_attractor
+pub struct Chen;
+
+impl Attractor for Chen {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 35.0), ("b", 3.0), ("c", 28.0)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, c, ..] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            a * (y - x),
+            (c - a) * x - x * z + c * y,
+            x * y - b * z,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 10.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(0.0, 0.0, 27.0), 28.0)
+    }
+
+    /// Its wings lie along the diagonal between the x and y axes, they are seen side by side from across it.
+    fn camera(&self) -> CameraView {
+        CameraView::around(vec3(0.0, 0.0, 27.0), vec3(1.0, -1.0, 0.5), 28.0)
+    }
+
+    fn time_scale(&self) -> f32 {
+        0.6
+    }
+}
+
+
+pub struct Dadras;
+
+impl Attractor for Dadras {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 3.0), ("b", 2.7), ("c", 1.7), ("d", 2.0), ("e", 9.0)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, c, d, e, _] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            y - a * x + b * y * z,
+            c * y - x * z + z,
+            d * x * y - e * z,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 2.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(-0.5, -2.0, 0.0), 12.0)
+    }
+
+    fn time_scale(&self) -> f32 {
+        3.0
+    }
+}
+
+
+/// The attractor from Sprott's "A dynamical system with a strange attractor and invariant tori" (2014).
+pub struct Sprott;
+
+impl Attractor for Sprott {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 2.07), ("b", 1.79)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, ..] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            y + a * x * y + x * z,
+            1.0 - b * x * x + y * z,
+            x - x * x - y * y,
+        )
+    }
+
+    /// Its basin is small, most points further away escape to infinity.
+    fn initial_region(&self) -> (Point3, f32) {
+        (vec3(0.63, 0.47, -0.54), 0.25)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(0.75, 0.0, 0.0), 1.1)
+    }
+
+    fn time_scale(&self) -> f32 {
+        10.0
+    }
+}
+
+
+/// The three-scroll unified chaotic system, TSUCS1.
+pub struct ThreeScroll;
+
+impl Attractor for ThreeScroll {
+    fn coefficients(&self) -> &'static [(&'static str, f32)] {
+        &[("a", 40.0), ("b", 55.0), ("c", 1.833), ("d", 0.16), ("e", 0.65), ("f", 20.0)]
+    }
+
+    fn derivative(&self, position: Point3, coefficients: &Coefficients) -> Vec3 {
+        let [a, b, c, d, e, f] = *coefficients;
+        let [x, y, z] = position.to_array();
+
+        vec3(
+            a * (y - x) + d * x * z,
+            b * x - x * z + f * y,
+            c * z + x * y - e * x * x,
+        )
+    }
+
+    fn initial_region(&self) -> (Point3, f32) {
+        (Vec3::ZERO, 1.0)
+    }
+
+    fn bounds(&self, _coefficients: &Coefficients) -> (Point3, f32) {
+        (vec3(10.0, 0.0, 100.0), 110.0)
+    }
+
+    fn time_scale(&self) -> f32 {
+        2.0
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AttractorKind {
+    Lorenz,
+    Rossler,
+    Thomas,
+    Aizawa,
+    Halvorsen,
+    Chen,
+    Dadras,
+    Sprott,
+    ThreeScroll,
+}
+
+impl AttractorKind {
+    pub const ALL: [AttractorKind; 9] = [
+        AttractorKind::Lorenz,
+        AttractorKind::Rossler,
+        AttractorKind::Thomas,
+        AttractorKind::Aizawa,
+        AttractorKind::Halvorsen,
+        AttractorKind::Chen,
+        AttractorKind::Dadras,
+        AttractorKind::Sprott,
+        AttractorKind::ThreeScroll,
+    ];
+
+    /// The names of all kinds, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "lorenz",
+        "rossler",
+        "thomas",
+        "aizawa",
+        "halvorsen",
+        "chen",
+        "dadras",
+        "sprott",
+        "three-scroll",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|kind| kind == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+
+    pub fn attractor(&self) -> &'static dyn Attractor {
+        match self {
+            AttractorKind::Lorenz => &Lorenz,
+            AttractorKind::Rossler => &Rossler,
+            AttractorKind::Thomas => &Thomas,
+            AttractorKind::Aizawa => &Aizawa,
+            AttractorKind::Halvorsen => &Halvorsen,
+            AttractorKind::Chen => &Chen,
+            AttractorKind::Dadras => &Dadras,
+            AttractorKind::Sprott => &Sprott,
+            AttractorKind::ThreeScroll => &ThreeScroll,
+        }
+    }
+
+    /// The names of the coefficients any of the attractors take, each once, in order of appearance.
+    pub fn coefficient_names() -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for kind in Self::ALL {
+            for (name, _) in kind.attractor().coefficients() {
+                if !names.contains(name) {
+                    names.push(*name);
+                }
+            }
+        }
+
+        names
+    }
+}
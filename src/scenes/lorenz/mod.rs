@@ -1,81 +1,281 @@
 use std::cell::RefCell;
 
-use lazy_static::lazy_static;
 use nannou::prelude::*;
 use nannou::{App, Frame};
 use nannou::app::Builder;
 use nannou::color::Alpha;
 use nannou::event::Update;
-use nannou::geom::pt3;
-use rand::{Rng, thread_rng};
-use clap::Parser;
+use rand::{Rng, SeedableRng};
 use async_trait::async_trait;
-use nannou::glam::Vec4Swizzles;
 use nannou::wgpu::{DeviceDescriptor, Limits};
-use crate::{Args, SceneArgs};
+use serde_json::{json, Value};
 use crate::math_3d::Camera;
+use crate::math_3d::clip::{clip_segment, project_point, to_screen};
 use crate::math_3d::controls::{CameraControls, CenteredCameraControls};
+use crate::math_3d::depth::{back_to_front, DepthCue, DepthCueOptions};
+use crate::colormap::{age_fraction, ColorSample, ColormapOptions};
+use crate::integrator::IntegratorOptions;
 use crate::particle::Particle3;
-use crate::scenes::Scene;
+use crate::scenes::lorenz::attractors::{Attractor, AttractorKind, Coefficients, default_coefficients, Lorenz, MAX_COEFFICIENTS};
+use crate::plot::Plottable;
+use crate::render::{Painter, RenderContext};
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::{ModelInstance, Scene, SceneInstance, WindowOptions};
+use crate::simulation::{SceneRng, seeded_rng, Simulation};
+use crate::snapshot::{check_settings, field, restore_all, restored, settings_json, Snapshot, snapshot_all, SnapshotFile, u32_field};
+use crate::timestep::{FixedTimestep, TimestepOptions};
 
+pub mod attractors;
 
-const BACKGROUND_COLOR: Srgb<u8> = BLANCHEDALMOND;
 
-// Can't inject parameters into the nannou model yet, so we need to use lazy_static
-// to access them as global variables.
-// https://github.com/nannou-org/nannou/issues/793
-lazy_static! {
-    static ref OPTIONS: LorenzOptions = LorenzOptions::from_args(&Args::parse());
-}
+/// Lorenz time runs at a tenth of wall clock time, in small steps to keep the integration stable.
+const DEFAULT_TIMESTEP: TimestepOptions = TimestepOptions {
+    step_size: 1.0 / 1200.0,
+    max_sub_steps: 64,
+    time_scale: 0.1,
+};
+
+/// About the highest speed on the attractors in reaches per unit of Lorenz time, the end of the gradient when coloring by speed.
+const COLORMAP_SPEED: f32 = 9.0;
+
+/// Particles further than this many reaches from the middle of the attractor have escaped it, and respawn.
+const ESCAPE_DISTANCE: f32 = 10.0;
 
+/// Depth cueing spans this many reaches in front of and behind the middle of the attractor.
+/// Particles further out, on their way to escaping it, are still drawn, cued like the front or the back.
+const DEPTH_RANGE: f32 = 1.5;
 
 pub struct LorenzScene {
-    model_fn: fn(app: &App) -> Model,
+    /// The name the scene's snapshots are saved under.
+    name: &'static str,
+    options: LorenzOptions,
     update_fn: fn(app: &App, model: &mut Model, _update: Update),
-    view_fn: fn(app: &App, model: &Model, frame: Frame),
+    advance_fn: fn(model: &mut Model, context: &RenderContext),
+    draw_fn: fn(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32),
     event_fn: fn(app: &App, model: &mut Model, event: Event),
+    config: Option<ConfigWatcher>,
+    snapshots: Option<SnapshotFile>,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct LorenzOptions {
-    pub rho: f32,  // https://en.wikipedia.org/wiki/Lorenz_system
-    pub sigma: f32,
-    pub beta: f32,
+    /// The attractor the particles follow, the Lorenz attractor in the Lorenz scene.
+    pub attractor: AttractorKind,
+    pub coefficients: Coefficients,
+    /// The seed for the scene's RNG, a random one is picked when `None`.
+    pub seed: Option<u32>,
+    pub particle_count: usize,
+    pub particle_radius: f32,
+    pub particle_color: Srgb<u8>,
+    /// Colors the particles by an attribute like their speed or height, instead of the particle color.
+    pub colormap: ColormapOptions,
+    pub particle_alpha: f32,
+    /// Particles spawn at most this far from the middle of the attractor's initial region along every axis.
+    pub spawn_extent: f32,
+    /// How the particles follow the attractor's equations.
+    pub integrator: IntegratorOptions,
+    /// The palette's background is used when it isn't set.
+    pub background_color: Option<Srgb<u8>>,
+    /// The alpha of the background laid over every frame, lower values smear the particles and their trails.
+    pub trail_fade: f32,
+    /// The amount of past positions, one per step, the particles' trails are drawn through.
+    pub trail_length: usize,
+    /// Fades and shrinks the particles further from the camera.
+    pub depth_cue: DepthCueOptions,
+    pub window: WindowOptions,
+    pub timestep: TimestepOptions,
 }
 
-impl LorenzOptions {
-    pub fn from_args(cli_args: &Args) -> Self {
-        match cli_args.scene {
-            SceneArgs::Lorenz { rho, sigma, beta } => {
-                Self {
-                    rho,
-                    sigma,
-                    beta,
-                }
+impl Default for LorenzOptions {
+    fn default() -> Self {
+        Self {
+            attractor: AttractorKind::Lorenz,
+            coefficients: default_coefficients(&Lorenz),
+            seed: None,
+            particle_count: 1000,
+            particle_radius: 4.0,
+            particle_color: BLACK,
+            colormap: ColormapOptions::with_palette("parchment"),
+            particle_alpha: 0.99,
+            spawn_extent: 10.0,
+            integrator: IntegratorOptions::default(),
+            background_color: None,
+            trail_fade: 1.0,
+            trail_length: 200,
+            depth_cue: DepthCueOptions::default(),
+            window: WindowOptions {
+                width: 1024,
+                height: 768,
             },
-            _ => panic!("Can't construct LorenzOptions from this scene type"),
+            timestep: DEFAULT_TIMESTEP,
         }
     }
 }
 
+impl LorenzOptions {
+    pub fn parameters() -> Vec<Parameter> {
+        let defaults = Self::default();
+        let [sigma, rho, beta, ..] = defaults.coefficients;
+        let mut parameters = vec![
+            Parameter::float("rho", "The rho from the Lorenz equation, see wikipedia", rho),
+            Parameter::float("sigma", "The sigma from the Lorenz equation, see wikipedia", sigma),
+            Parameter::float("beta", "The beta from the Lorenz equation, see wikipedia", beta),
+            Parameter::float("spawn-extent", "Particles spawn at most this far from the origin along every axis", defaults.spawn_extent)
+                .with_range(1.0, 10_000.0),
+        ];
+        parameters.extend(Self::shared_parameters(&defaults));
+
+        parameters
+    }
+
+    /// The parameters of the `attractor` scene, which picks the attractor by its kind.
+    /// The coefficients of all kinds are offered, those that aren't given keep the kind's usual values.
+    pub fn attractor_parameters() -> Vec<Parameter> {
+        let defaults = Self::default();
+        let mut parameters = vec![
+            Parameter::choice("kind", "The attractor the particles follow", AttractorKind::NAMES, defaults.attractor.name()),
+        ];
+        parameters.extend(AttractorKind::coefficient_names().into_iter().map(|name|
+            Parameter::optional_float(name, "A coefficient of the attractor, its usual value for the kind if not given, ignored by kinds without it")
+        ));
+        parameters.push(Parameter::optional_float("spawn-extent", "Particles spawn at most this far from the middle of the attractor's initial region along every axis, the kind's own extent if not given")
+            .with_range(0.001, 10_000.0));
+        parameters.extend(Self::shared_parameters(&defaults));
+
+        parameters
+    }
+
+    /// The parameters both the Lorenz and the attractor scene take.
+    fn shared_parameters(defaults: &LorenzOptions) -> Vec<Parameter> {
+        let mut parameters = vec![
+            Parameter::seed(),
+            Parameter::integer("particle-count", "The amount of particles", defaults.particle_count as i64)
+                .with_range(1.0, 1_000_000.0),
+            Parameter::float("particle-radius", "The radius of the particles", defaults.particle_radius)
+                .with_range(0.0, 1000.0),
+            Parameter::color("particle-color", "The color of the particles", defaults.particle_color),
+            Parameter::float("particle-alpha", "The alpha of the particles", defaults.particle_alpha)
+                .with_range(0.0, 1.0),
+            Parameter::optional_color("background-color", "The color of the background, the palette's background if not given"),
+            Parameter::float("trail-fade", "The alpha of the background laid over every frame, lower values smear the particles and their trails", defaults.trail_fade)
+                .with_range(0.0, 1.0),
+            Parameter::integer("trail-length", "The amount of past positions, one per step, the particles' trails are drawn through, 0 draws no trails", defaults.trail_length as i64)
+                .with_range(0.0, 10_000.0),
+        ];
+        parameters.extend(ColormapOptions::parameters(defaults.colormap));
+        parameters.extend(IntegratorOptions::parameters(defaults.integrator));
+        parameters.extend(DepthCueOptions::parameters(defaults.depth_cue));
+        parameters.extend(WindowOptions::parameters(defaults.window));
+        parameters.extend(TimestepOptions::parameters(defaults.timestep));
+
+        parameters
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        let mut coefficients = [0.0; MAX_COEFFICIENTS];
+        coefficients[..3].copy_from_slice(&[values.float("sigma"), values.float("rho"), values.float("beta")]);
+
+        Self::from_shared_parameters(values, AttractorKind::Lorenz, coefficients, values.float("spawn-extent"))
+    }
+
+    pub fn from_attractor_parameters(values: &ParameterValues) -> Self {
+        let kind = AttractorKind::from_name(values.choice("kind")).unwrap();
+        let attractor = kind.attractor();
+
+        let mut coefficients = default_coefficients(attractor);
+        for (coefficient, (name, _)) in coefficients.iter_mut().zip(attractor.coefficients()) {
+            if let Some(value) = values.optional_float(name) {
+                *coefficient = value;
+            }
+        }
+        let spawn_extent = values.optional_float("spawn-extent")
+            .unwrap_or(attractor.initial_region().1);
+
+        Self::from_shared_parameters(values, kind, coefficients, spawn_extent)
+    }
+
+    fn from_shared_parameters(values: &ParameterValues, attractor: AttractorKind, coefficients: Coefficients, spawn_extent: f32) -> Self {
+        Self {
+            attractor,
+            coefficients,
+            seed: values.seed(),
+            particle_count: values.integer("particle-count") as usize,
+            particle_radius: values.float("particle-radius"),
+            particle_color: values.color("particle-color"),
+            colormap: ColormapOptions::from_parameters(values),
+            particle_alpha: values.float("particle-alpha"),
+            spawn_extent,
+            integrator: IntegratorOptions::from_parameters(values),
+            background_color: values.optional_color("background-color"),
+            trail_fade: values.float("trail-fade"),
+            trail_length: values.integer("trail-length") as usize,
+            depth_cue: DepthCueOptions::from_parameters(values),
+            window: WindowOptions::from_parameters(values),
+            timestep: TimestepOptions::from_parameters(values),
+        }
+    }
+
+    /// Whether going from `self` to `other` needs the particles to be respawned,
+    /// the other options can be applied to the running scene.
+    pub fn needs_reset(&self, other: &LorenzOptions) -> bool {
+        self.attractor != other.attractor
+            || self.seed != other.seed
+            || self.particle_count != other.particle_count
+            || self.spawn_extent != other.spawn_extent
+    }
+
+    /// The background color, which comes from the palette unless it is set.
+    pub fn background(&self) -> Srgb<u8> {
+        self.background_color.unwrap_or(self.colormap.palette.background)
+    }
+}
+
 
 #[async_trait]
 impl Scene for LorenzScene {
     type SceneOptions = LorenzOptions;
     type Model = Model;
 
+    const NAME: &'static str = "lorenz";
+    const DESCRIPTION: &'static str = "A 3D simulation of a Lorenz attractor";
+
+    fn parameters() -> Vec<Parameter> {
+        LorenzOptions::parameters()
+    }
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions {
+        LorenzOptions::from_parameters(values)
+    }
 
-    fn new_scene(_options: &Self::SceneOptions) -> Self {
+    fn new_scene(options: &Self::SceneOptions) -> Self {
         Self {
-            model_fn: model,
+            name: Self::NAME,
+            options: *options,
             update_fn: update,
-            view_fn: view,
+            advance_fn: advance,
+            draw_fn: draw,
             event_fn: event,
+            config: None,
+            snapshots: None,
         }
     }
 
-    async fn app(&self) -> Builder<Self::Model> {
-        let model = Model::new();
+    fn watch_config(&mut self, watcher: ConfigWatcher) {
+        self.config = Some(watcher);
+    }
+
+    fn snapshot_file(&mut self, file: SnapshotFile) {
+        self.snapshots = Some(file);
+    }
+
+    async fn app(&self) -> Result<Builder<Self::Model>, String> {
+        let mut model = Model::new(&self.options, self.name);
+        model.config = self.config.clone();
+        if let Some(snapshots) = self.snapshots.clone() {
+            snapshots.resume(&mut model)?;
+            model.snapshots = snapshots;
+        }
 
         thread_local!(static MODEL: RefCell<Option<Model>> = Default::default());
         MODEL.with(|m| m.borrow_mut().replace(model));
@@ -90,167 +290,470 @@ impl Scene for LorenzScene {
                     ..Default::default()
                 };
 
+                let model = MODEL.with(|m| m.borrow_mut().take().unwrap());
+                let window = model.options.window;
+
                 app.new_window()
                     .device_descriptor(device_descriptor)
                     .view(view)
                     .title("n0ls Lorenz")
+                    .size(window.width, window.height)
                     .build_async()
                     .await
                     .unwrap();
 
-                MODEL.with(|m| m.borrow_mut().take().unwrap())
+                model
             })
         });
 
-        builder
+        Ok(builder
             .update(self.update_fn)
-            .event(self.event_fn)
+            .event(self.event_fn))
+    }
+
+    fn instance(&self) -> Box<dyn SceneInstance> {
+        Box::new(ModelInstance {
+            model: Model::new(&self.options, self.name),
+            update_fn: self.update_fn,
+            advance_fn: self.advance_fn,
+            event_fn: self.event_fn,
+            draw_fn: self.draw_fn,
+            background: self.options.background(),
+        })
+    }
+}
+
+
+/// The Lorenz scene's camera and rendering, with the attractor picked by `--kind`.
+pub struct AttractorScene {
+    scene: LorenzScene,
+}
+
+#[async_trait]
+impl Scene for AttractorScene {
+    type SceneOptions = LorenzOptions;
+    type Model = Model;
+
+    const NAME: &'static str = "attractor";
+    const DESCRIPTION: &'static str = "A 3D simulation of a strange attractor, like the Lorenz, Rössler or Thomas attractor";
+
+    fn parameters() -> Vec<Parameter> {
+        LorenzOptions::attractor_parameters()
+    }
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions {
+        LorenzOptions::from_attractor_parameters(values)
+    }
 
-        // Old code
-        // nannou::app(self.model_fn)
-        //     .update(self.update_fn)
-        //     .event(self.event_fn)
-        //     .simple_window(self.view_fn)
-        //     .size(1800, 1200)
+    fn new_scene(options: &Self::SceneOptions) -> Self {
+        Self {
+            scene: LorenzScene {
+                name: Self::NAME,
+                update_fn: update_attractor,
+                ..LorenzScene::new_scene(options)
+            },
+        }
+    }
+
+    fn watch_config(&mut self, watcher: ConfigWatcher) {
+        self.scene.watch_config(watcher);
+    }
+
+    fn snapshot_file(&mut self, file: SnapshotFile) {
+        self.scene.snapshot_file(file);
+    }
+
+    async fn app(&self) -> Result<Builder<Self::Model>, String> {
+        self.scene.app().await
+    }
+
+    fn instance(&self) -> Box<dyn SceneInstance> {
+        self.scene.instance()
     }
 }
 
 
 pub struct Model {
-    pub particles: Vec<Particle3>,
-    pub rho: f32,  // https://en.wikipedia.org/wiki/Lorenz_system
-    pub sigma: f32,
-    pub beta: f32,
+    pub options: LorenzOptions,
+    /// The seed the scene's RNG was created with.
+    pub seed: u32,
+    pub simulation: LorenzSimulation,
+    pub timestep: FixedTimestep,
     // pub camera_angle: f32,
     pub camera: Camera,
     pub camera_controls: Box<dyn CameraControls>,
+    /// How much further the camera started out from the attractor than from the Lorenz attractor,
+    /// which the particles are scaled by so they look as big.
+    pub view_scale: f32,
+    /// The config file whose changes are applied while running, if any.
+    pub config: Option<ConfigWatcher>,
+    pub snapshots: SnapshotFile,
 }
 
 impl Model {
-    pub fn new() -> Self {
-        let particles = vec![0; 1000].into_iter().map(|_| {
+    /// `name` is the name of the scene, which its snapshots are saved under.
+    pub fn new(options: &LorenzOptions, name: &'static str) -> Self {
+        let (seed, mut rng) = seeded_rng(options.seed);
+        let (camera, camera_controls, view_scale) = attractor_camera(options.attractor);
+
+        Model {
+            options: *options,
+            seed,
+            simulation: LorenzSimulation::new(&mut rng, options),
+            timestep: FixedTimestep::new(options.timestep),
+            camera,
+            camera_controls,
+            view_scale,
+            config: None,
+            snapshots: SnapshotFile::for_scene(name),
+        }
+    }
+
+    /// Applies new options to the running scene.
+    /// The particles are only respawned when a structural option like the seed or particle count changes,
+    /// the rest is applied in place. The window size only takes effect on the next launch.
+    pub fn reconfigure(&mut self, options: &LorenzOptions) {
+        if self.options.needs_reset(options) {
+            let (seed, mut rng) = seeded_rng(options.seed);
+            self.seed = seed;
+            self.simulation = LorenzSimulation::new(&mut rng, options);
+            if self.options.attractor != options.attractor {
+                (self.camera, self.camera_controls, self.view_scale) = attractor_camera(options.attractor);
+            }
+        } else {
+            self.simulation.coefficients = options.coefficients;
+            self.simulation.integrator = options.integrator;
+            self.simulation.trail_length = options.trail_length;
+            for particle in self.simulation.particles.iter_mut() {
+                particle.color = Alpha {
+                    color: options.particle_color.into_format(),
+                    alpha: options.particle_alpha,
+                };
+                particle.radius = options.particle_radius;
+            }
+        }
+
+        self.timestep.set_options(options.timestep);
+        self.options = *options;
+    }
+}
+
+impl Plottable for Model {}
+
+/// The options aren't restored, a snapshot is loaded into a scene that was started with them.
+/// The ones that change how the particles move are written as settings, and a snapshot taken with others is rejected.
+impl Snapshot for Model {
+    fn snapshot(&self) -> Value {
+        json!({
+            "settings": self.settings(),
+            "seed": self.seed,
+            "timestep": self.timestep.snapshot(),
+            "camera": self.camera.snapshot(),
+            "simulation": self.simulation.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        check_settings(snapshot, &self.settings())?;
+        let seed = u32_field(snapshot, "seed")?;
+        let timestep = restored(&self.timestep, snapshot, "timestep")?;
+        let camera = Camera::from_snapshot(field(snapshot, "camera")?)?;
+        // Restored last, since it's left as it was when its own part is invalid
+        self.simulation.restore(field(snapshot, "simulation")?)?;
+
+        self.seed = seed;
+        self.timestep = timestep;
+        self.camera = camera;
+
+        Ok(())
+    }
+}
+
+impl Model {
+    fn settings(&self) -> Value {
+        let options = &self.options;
+        settings_json([
+            json!({
+                "spawn-extent": options.spawn_extent,
+            }),
+            options.integrator.settings(),
+            options.timestep.settings(),
+        ])
+    }
+}
+
+/// The camera looking at the attractor from where it suggests, with controls that move it at a pace fitting the attractor's size.
+/// Also returns how much further away that is than for the Lorenz attractor.
+fn attractor_camera(kind: AttractorKind) -> (Camera, Box<dyn CameraControls>, f32) {
+    let view = kind.attractor().camera();
+    let view_scale = view.distance() / Lorenz.camera().distance();
+
+    let view_direction = (view.target - view.position).normalize();
+    let right = view_direction.cross(vec3(0.0, 1.0, 0.0)).normalize();
+    let up = right.cross(view_direction).normalize(); // calculate the real up vector
+
+    // The controls keep the camera pointed at the target while the window runs,
+    // headless renders never apply them so it has to start out that way.
+    let camera = Camera::new_perspective(
+        view.position,
+        view_direction,
+        up,
+        0.25,
+        1.0, // so that WASM works
+    );
+    let controls = CenteredCameraControls::new(
+        view.target,
+        100.0 * view_scale,
+        100.0 * view_scale,
+    );
+
+    (camera, Box::new(controls), view_scale)
+}
+
+/// The window-free part of the Lorenz scene, which follows particles along any attractor.
+pub struct LorenzSimulation {
+    pub particles: Vec<Particle3>,
+    pub attractor: AttractorKind,
+    pub coefficients: Coefficients,
+    /// Particles spawn at most this far from the middle of the attractor's initial region,
+    /// which is where the particles that escape the attractor respawn.
+    pub spawn_extent: f32,
+    pub integrator: IntegratorOptions,
+    /// How many of their latest positions the particles keep in their trails.
+    pub trail_length: usize,
+    pub rng: SceneRng,
+}
+
+impl LorenzSimulation {
+    pub fn new<R: Rng>(rng: &mut R, options: &LorenzOptions) -> Self {
+        let (spawn_center, _) = options.attractor.attractor().initial_region();
+
+        let particles = vec![0; options.particle_count].into_iter().map(|_| {
             Particle3::new(
-                pt3(
-                    thread_rng().gen_range(-10..10) as f32,
-                    thread_rng().gen_range(-10..10) as f32,
-                    thread_rng().gen_range(-10..10) as f32,
-                ),
+                spawn_position(rng, spawn_center, options.spawn_extent),
                 Alpha {
-                    color: rgb(0.0, 0.0, 0.0),
-                    alpha: 0.99,
+                    color: options.particle_color.into_format(),
+                    alpha: options.particle_alpha,
                 },
-                4.0,
+                options.particle_radius,
             )
         }).collect::<Vec<_>>();
 
-        // let cam_position = pt3(300.0, 10.0, 10.0);
-        let cam_position = pt3(10.926121, 7.850386, 232.43593);
-        let view_center = vec3(0.0, 0.0, 0.0);
-        let view_direction = (view_center - vec3(10.0, 10.0, 10.0)).normalize();
-        let up = vec3(0.0, 1.0, 0.0);
-        let right = view_direction.cross(up).normalize();
-        let up = right.cross(view_direction).normalize(); // calculate the real up vector
-
-        #[cfg(not(target_family = "wasm"))]
-        return Model {
+        Self {
             particles,
-            rho: OPTIONS.rho,
-            sigma: OPTIONS.sigma,
-            beta: OPTIONS.beta,
-            camera: Camera::new_perspective(
-                cam_position,
-                view_direction,
-                up,
-                0.25,
-                // win.w() / win.h(),
-                1.0, // so that WASM works
-            ),
-            camera_controls: Box::new(CenteredCameraControls::new(
-                view_center,
-                100.0,
-                100.0,
-            )),
-        };
+            attractor: options.attractor,
+            coefficients: options.coefficients,
+            spawn_extent: options.spawn_extent,
+            integrator: options.integrator,
+            trail_length: options.trail_length,
+            rng: SceneRng::seed_from_u64(rng.gen()),
+        }
+    }
 
-        #[cfg(target_family = "wasm")]
-        Model {
-            particles,
-            rho: 28.0,
-            sigma: 10.0,
-            beta: 2.66667,
-            camera: Camera::new_perspective(
-                cam_position,
-                view_direction,
-                up,
-                0.25,
-                1.0, // so that WASM works
-            ),
-            camera_controls: Box::new(CenteredCameraControls::new(
-                view_center,
-                100.0,
-                100.0,
-            )),
+    /// Colors the particles by the colormap's attribute, unless they keep the particle color.
+    /// Distances and heights are relative to how far the attractor reaches from its middle.
+    pub fn apply_colormap(&mut self, colormap: &ColormapOptions) {
+        let (center, reach) = self.attractor.attractor().bounds(&self.coefficients);
+
+        for particle in self.particles.iter_mut() {
+            let sample = ColorSample {
+                angle: particle.velocity.y.atan2(particle.velocity.x),
+                speed: particle.velocity.length() / (COLORMAP_SPEED * reach),
+                age: age_fraction(particle.age, particle.lifetime),
+                distance: particle.position.distance(center) / reach,
+                height: (particle.position.z - center.z + reach) / (2.0 * reach),
+            };
+
+            if let Some(color) = colormap.color(&sample) {
+                particle.color.color = color;
+            }
+        }
+    }
+}
+
+impl Snapshot for LorenzSimulation {
+    fn snapshot(&self) -> Value {
+        json!({
+            "attractor": self.attractor.name(),
+            "coefficients": self.coefficients,
+            "particles": snapshot_all(&self.particles),
+            "rng": self.rng.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let name = field(snapshot, "attractor")?
+            .as_str()
+            .ok_or_else(|| "`attractor` should be a name".to_string())?;
+        let attractor = AttractorKind::from_name(name)
+            .ok_or_else(|| format!("`attractor` should be one of {}, got \"{}\"", AttractorKind::NAMES.join(", "), name))?;
+
+        let coefficients = field(snapshot, "coefficients")?
+            .as_array()
+            .filter(|coefficients| coefficients.len() == MAX_COEFFICIENTS)
+            .ok_or_else(|| format!("`coefficients` should be a list of {} numbers", MAX_COEFFICIENTS))?;
+        let mut parsed = [0.0; MAX_COEFFICIENTS];
+        for (coefficient, value) in parsed.iter_mut().zip(coefficients) {
+            *coefficient = value.as_f64()
+                .ok_or_else(|| format!("`coefficients` should be a list of {} numbers", MAX_COEFFICIENTS))? as f32;
+        }
+
+        let particles = restore_all(snapshot, "particles")?;
+        let rng = restored(&self.rng, snapshot, "rng")?;
+
+        self.attractor = attractor;
+        self.coefficients = parsed;
+        self.particles = particles;
+        self.rng = rng;
+
+        Ok(())
+    }
+}
+
+impl Simulation for LorenzSimulation {
+    type Particle = Particle3;
+
+    /// Moves the particles along the attractor, in its own time.
+    /// Particles that escape it, which some attractors let points far from them do, respawn in its initial region.
+    fn step(&mut self, dt: f32) {
+        let attractor = self.attractor.attractor();
+        let time_scale = attractor.time_scale();
+        let (center, reach) = attractor.bounds(&self.coefficients);
+        let (spawn_center, _) = attractor.initial_region();
+
+        for particle in self.particles.iter_mut() {
+            let coefficients = &self.coefficients;
+            let position = self.integrator.step(particle.position, dt, |position| attractor.derivative(position, coefficients) * time_scale);
+            // The average velocity over the step, which is the derivative at the start for Euler
+            particle.velocity = (position - particle.position) / dt;
+            particle.position = position;
+
+            if !particle.position.is_finite() || particle.position.distance(center) > ESCAPE_DISTANCE * reach {
+                particle.respawn(spawn_position(&mut self.rng, spawn_center, self.spawn_extent), None);
+            }
+
+            particle.record_trail(self.trail_length);
         }
     }
+
+    fn particles(&self) -> &[Particle3] {
+        &self.particles
+    }
 }
 
 
-fn model(app: &App) -> Model {
-    // let win = app.window_rect();
-    Model::new()
+/// A random position in the box reaching `extent` from the center along every axis.
+fn spawn_position<R: Rng>(rng: &mut R, center: Point3, extent: f32) -> Point3 {
+    center + vec3(
+        rng.gen_range(-extent..=extent),
+        rng.gen_range(-extent..=extent),
+        rng.gen_range(-extent..=extent),
+    )
 }
 
+
 fn view(app: &App, model: &Model, frame: Frame) {
-    let win = app.window_rect();
-    let draw = app.draw();
-    // let time_passed = app.duration.since_prev_update.as_secs_f32();
+    let mut draw = app.draw();
 
     if app.elapsed_frames() < 2 {
-        draw.background().color(BACKGROUND_COLOR);
+        draw.background().color(model.options.background());
     }
 
-    draw.rect().wh(win.wh()).xy(win.xy()).color(Alpha { color: BACKGROUND_COLOR, alpha: 0.02 });
+    self::draw(&RenderContext::from_app(app), model, &mut draw, 1.0);
+    draw.to_frame(app, &frame).unwrap();
+}
+
+fn draw(context: &RenderContext, model: &Model, painter: &mut dyn Painter, opacity: f32) {
+    let win = context.bounds;
+    // let time_passed = context.frame_time;
+
+    painter.rect(win, Alpha { color: model.options.background().into_format(), alpha: model.options.trail_fade * opacity });
 
     let transformation_matrix = model.camera.get_transformation_matrix();
+    let (center, reach) = model.simulation.attractor.attractor().bounds(&model.simulation.coefficients);
+    let depth_cue = DepthCue::new(&model.camera, center, DEPTH_RANGE * reach, model.options.depth_cue, model.options.background());
+    let particles = &model.simulation.particles;
+
+    for (i, depth) in back_to_front(&model.camera, particles.iter().map(|particle| particle.position)) {
+        let particle = &particles[i];
+        draw_trail(painter, particle, &model.camera, &depth_cue, win, model.view_scale, opacity);
+
+        let (color, size) = depth_cue.apply(depth, particle.color, particle.radius * 50.0 * model.view_scale);
+        let clip = particle.transform_position(transformation_matrix);
+        let Some((center, radius)) = project_point(clip, size, win) else {
+            continue;
+        };
+        painter.ellipse(
+            center,
+            radius,
+            Alpha { color: color.color, alpha: color.alpha * opacity },
+        );
+    }
+}
+
+/// Draws the trail as a line through its projected positions,
+/// which thins out and fades from the particle's size and alpha at its head to nothing at its tail.
+/// The parts behind the camera are clipped off.
+fn draw_trail(painter: &mut dyn Painter, particle: &Particle3, camera: &Camera, depth_cue: &DepthCue, win: Rect, view_scale: f32, opacity: f32) {
+    let transformation_matrix = camera.get_transformation_matrix();
+    let segments = particle.trail.len().saturating_sub(1);
+    let clips = particle.trail.iter()
+        .map(|position| transformation_matrix * Vec4::from((*position, 1.0)));
+    let ends = particle.trail.iter().skip(1);
+
+    for (i, ((start, end), position)) in clips.clone().zip(clips.skip(1)).zip(ends).enumerate() {
+        let Some((start, end)) = clip_segment(start, end) else {
+            continue;
+        };
 
-    for particle in model.particles.iter() {
-        let new_particle_position = particle.transform_position(transformation_matrix);
-        let nannou_coordinate_transformation = Mat4::from_diagonal(vec4(win.x.end, win.y.end, 1.0, 1.0));
-        let new_particle_position = nannou_coordinate_transformation * new_particle_position;
-        draw.ellipse()
-            .radius(particle.radius / new_particle_position.w * 50.0)
-            .color(particle.color)
-            .xy(new_particle_position.xy() / new_particle_position.w);
+        let fraction = (i + 1) as f32 / segments as f32;
+        let (color, size) = depth_cue.apply(camera.depth(*position), particle.color, 2.0 * particle.radius * 50.0 * view_scale);
+        painter.line(
+            to_screen(start, win),
+            to_screen(end, win),
+            size / end.w * fraction,
+            Alpha { color: color.color, alpha: color.alpha * opacity * fraction },
+        );
     }
-    draw.to_frame(app, &frame).unwrap();
 }
 
 fn update(app: &App, model: &mut Model, _update: Update) {
-    let time_passed = app.duration.since_prev_update.as_secs_f32();
-
-    let sigma = model.sigma;
-    let rho = model.rho;
-    let beta = model.beta;
+    update_with(app, model, LorenzOptions::from_parameters);
+}
 
-    for particle in model.particles.iter_mut() {
-        let x = particle.position.x;
-        let y = particle.position.y;
-        let z = particle.position.z;
+/// The update of the attractor scene, whose config files hold the attractor scene's parameters.
+fn update_attractor(app: &App, model: &mut Model, _update: Update) {
+    update_with(app, model, LorenzOptions::from_attractor_parameters);
+}
 
-        // The Lorenz equations
-        let dx = sigma * (y - x);
-        let dy = x * (rho - z) - y;
-        let dz = x * y - beta * z;
+fn update_with(app: &App, model: &mut Model, options_fn: fn(&ParameterValues) -> LorenzOptions) {
+    let context = RenderContext::from_app(app);
 
-        let velocity = vec3(dx, dy, dz);
-        particle.position += velocity * time_passed / 10.0;
+    if let Some(values) = model.config.as_mut().and_then(|config| config.poll(context.frame_time)) {
+        model.reconfigure(&options_fn(&values));
     }
 
+    advance(model, &context);
+
     // model.camera_angle += time_passed * 0.1;
     model.camera_controls.apply_to_camera(&mut model.camera, app);
 }
 
+fn advance(model: &mut Model, context: &RenderContext) {
+    model.timestep.advance(&mut model.simulation, context.frame_time);
+    model.simulation.apply_colormap(&model.options.colormap);
+
+    // Particles are drawn until they escape
+    let (center, reach) = model.simulation.attractor.attractor().bounds(&model.simulation.coefficients);
+    model.camera.fit_far_plane(center, ESCAPE_DISTANCE * reach);
+}
+
 fn event(app: &App, model: &mut Model, event: Event) {
+    model.timestep.event(&event);
+
+    let snapshots = model.snapshots.clone();
+    snapshots.event(model, &event);
+
     model.camera_controls.event(app, event);
 
     // #[cfg(target_family = "wasm")]
@@ -261,4 +764,41 @@ fn event(app: &App, model: &mut Model, event: Event) {
     //     log(&format!("Camera direction: {:?}", model.camera.view_direction));
     //     log(&format!("Camera up: {:?}", model.camera.up));
     // }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn particles_outside_the_depth_range_are_drawn_until_they_escape() {
+        let context = RenderContext {
+            bounds: Rect::from_w_h(1800.0, 1200.0),
+            frame_time: 1.0 / 60.0,
+            elapsed_frames: 0,
+        };
+
+        for kind in AttractorKind::ALL {
+            let options = LorenzOptions {
+                attractor: kind,
+                coefficients: default_coefficients(kind.attractor()),
+                particle_count: 10,
+                ..LorenzOptions::default()
+            };
+            let mut model = Model::new(&options, "attractor");
+            advance(&mut model, &context);
+
+            let (center, reach) = kind.attractor().bounds(&options.coefficients);
+            let transformation_matrix = model.camera.get_transformation_matrix();
+            let view_direction = model.camera.view_direction.normalize();
+
+            // Behind the attractor, past the back of the depth cueing
+            for reaches in [2.0, 5.0, 0.99 * ESCAPE_DISTANCE] {
+                let position = center + view_direction * reaches * reach;
+                let clip = transformation_matrix * Vec4::from((position, 1.0));
+
+                assert!(project_point(clip, 1.0, context.bounds).is_some(), "{} not drawn at {} reaches", kind.name(), reaches);
+            }
+        }
+    }
+}
@@ -1,8 +1,19 @@
 use async_trait::async_trait;
+use nannou::prelude::*;
+use crate::plot::{Plottable, Trail};
+use crate::render::{Painter, RenderContext};
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::snapshot::{Snapshot, SnapshotFile};
 
 pub mod base3d;
+pub mod config;
+pub mod curl_flow;
+pub mod host;
 pub mod perlin_flow;
 pub mod lorenz;
+pub mod parameters;
+pub mod registry;
 
 
 #[async_trait]
@@ -10,7 +21,119 @@ pub trait Scene {
     type SceneOptions;
     type Model;
 
+    /// The name the scene is registered under, used for the CLI subcommand and on wasm.
+    const NAME: &'static str;
+    const DESCRIPTION: &'static str;
+
+    /// The parameters from which the scene options are built.
+    fn parameters() -> Vec<Parameter>;
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions;
+
     fn new_scene(options: &Self::SceneOptions) -> Self;
 
-    async fn app(&self) -> nannou::app::Builder<Self::Model>;
+    /// Makes the scene's app apply the changes made to its config file while it runs.
+    fn watch_config(&mut self, watcher: ConfigWatcher);
+
+    /// Makes the scene's app resume from the snapshot file if it exists,
+    /// and save to and load from it with `F5` and `F9`.
+    fn snapshot_file(&mut self, file: SnapshotFile);
+
+    /// Fails when the scene can't be resumed from its snapshot file.
+    async fn app(&self) -> Result<nannou::app::Builder<Self::Model>, String>;
+
+    /// Creates the scene without a window of its own, to be run inside the host app.
+    fn instance(&self) -> Box<dyn SceneInstance>;
+}
+
+
+/// The size of a scene's window.
+#[derive(Copy, Clone, Debug)]
+pub struct WindowOptions {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl WindowOptions {
+    pub fn parameters(defaults: WindowOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::integer("window-width", "The width of the window in pixels", defaults.width as i64)
+                .with_range(1.0, 8192.0),
+            Parameter::integer("window-height", "The height of the window in pixels", defaults.height as i64)
+                .with_range(1.0, 8192.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            width: values.integer("window-width") as u32,
+            height: values.integer("window-height") as u32,
+        }
+    }
+}
+
+
+/// A running scene behind a trait object, so the host app can switch between scenes
+/// and the `render` subcommand can run any scene without a window.
+pub trait SceneInstance {
+    fn update(&mut self, app: &App, update: Update);
+    /// Advances the scene by a frame without needing the `App`, which is all `update` does besides handling input.
+    fn advance(&mut self, context: &RenderContext);
+    fn event(&mut self, app: &App, event: Event);
+    /// Draws the scene with all its colors faded by `opacity`, which lies in `[0, 1]`.
+    fn draw(&self, context: &RenderContext, painter: &mut dyn Painter, opacity: f32);
+    /// The color the window is cleared with when switching to this scene.
+    fn background(&self) -> Srgb<u8>;
+    /// Loads the scene's state from the snapshot file if it exists.
+    fn resume(&mut self, file: &SnapshotFile) -> Result<(), String>;
+    /// See `Plottable::record_trails`.
+    fn record_trails(&mut self, min_distance: f32) -> bool;
+    fn trails(&self) -> Vec<Trail>;
+}
+
+/// Runs a scene through the same model, update, event and draw functions its own app uses.
+pub struct ModelInstance<M> {
+    pub model: M,
+    pub update_fn: fn(app: &App, model: &mut M, update: Update),
+    pub advance_fn: fn(model: &mut M, context: &RenderContext),
+    pub event_fn: fn(app: &App, model: &mut M, event: Event),
+    pub draw_fn: fn(context: &RenderContext, model: &M, painter: &mut dyn Painter, opacity: f32),
+    pub background: Srgb<u8>,
+}
+
+impl<M> SceneInstance for ModelInstance<M>
+    where
+        M: Snapshot + Plottable
+{
+    fn update(&mut self, app: &App, update: Update) {
+        (self.update_fn)(app, &mut self.model, update);
+    }
+
+    fn advance(&mut self, context: &RenderContext) {
+        (self.advance_fn)(&mut self.model, context);
+    }
+
+    fn event(&mut self, app: &App, event: Event) {
+        (self.event_fn)(app, &mut self.model, event);
+    }
+
+    fn draw(&self, context: &RenderContext, painter: &mut dyn Painter, opacity: f32) {
+        (self.draw_fn)(context, &self.model, painter, opacity);
+    }
+
+    fn background(&self) -> Srgb<u8> {
+        self.background
+    }
+
+    fn resume(&mut self, file: &SnapshotFile) -> Result<(), String> {
+        file.resume(&mut self.model)
+    }
+
+    fn record_trails(&mut self, min_distance: f32) -> bool {
+        self.model.record_trails(min_distance)
+    }
+
+    fn trails(&self) -> Vec<Trail> {
+        self.model.trails()
+    }
 }
@@ -0,0 +1,582 @@
+//! The parameter schema of a scene.
+//! Scenes describe their parameters once, from which the CLI, config files
+//! and the wasm entry point are built.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use clap::{Arg, ArgAction, ArgMatches, value_parser};
+use clap::builder::PossibleValuesParser;
+use nannou::prelude::*;
+use crate::palette::Palette;
+
+
+/// The type of value a parameter takes, along with its default and valid range.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParameterKind {
+    /// An on/off switch that is off by default.
+    /// On the command line `--name` turns it on and `--name=false` turns it off, e.g. when a config file turned it on.
+    Flag,
+    /// A number. Without a default it is left unset unless given.
+    Float { default: Option<f32>, min: f32, max: f32 },
+    Integer { default: i64, min: i64, max: i64 },
+    /// An optional seed for a random number generator.
+    Seed,
+    /// A color, written as a hex string like `"#ff8800"`. Without a default it is left unset unless given.
+    Color { default: Option<Srgb<u8>> },
+    /// One of a fixed set of names.
+    Choice { default: &'static str, choices: &'static [&'static str] },
+    /// A palette, written as anything `Palette::parse` reads.
+    Palette { default: Palette },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Parameter {
+    /// The kebab-case name, used as the CLI flag and as the key in JSON options.
+    pub name: &'static str,
+    pub description: &'static str,
+    pub kind: ParameterKind,
+}
+
+impl Parameter {
+    pub fn flag(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Flag,
+        }
+    }
+
+    pub fn float(name: &'static str, description: &'static str, default: f32) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Float { default: Some(default), min: f32::NEG_INFINITY, max: f32::INFINITY },
+        }
+    }
+
+    /// A number that is left unset unless given, for which the scene picks a fallback.
+    pub fn optional_float(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Float { default: None, min: f32::NEG_INFINITY, max: f32::INFINITY },
+        }
+    }
+
+    pub fn integer(name: &'static str, description: &'static str, default: i64) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Integer { default, min: i64::MIN, max: i64::MAX },
+        }
+    }
+
+    pub fn color(name: &'static str, description: &'static str, default: Srgb<u8>) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Color { default: Some(default) },
+        }
+    }
+
+    /// A color that is left unset unless given, for which the scene picks a fallback.
+    pub fn optional_color(name: &'static str, description: &'static str) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Color { default: None },
+        }
+    }
+
+    pub fn palette(name: &'static str, description: &'static str, default: Palette) -> Self {
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Palette { default },
+        }
+    }
+
+    pub fn choice(name: &'static str, description: &'static str, choices: &'static [&'static str], default: &'static str) -> Self {
+        assert!(choices.contains(&default), "The default of parameter {} is not one of its choices", name);
+
+        Self {
+            name,
+            description,
+            kind: ParameterKind::Choice { default, choices },
+        }
+    }
+
+    pub fn seed() -> Self {
+        Self {
+            name: "seed",
+            description: "The seed for the random number generator, picked at random if not given",
+            kind: ParameterKind::Seed,
+        }
+    }
+
+    /// Restricts a numeric parameter to the inclusive range `[min, max]`.
+    pub fn with_range(mut self, min: f64, max: f64) -> Self {
+        match &mut self.kind {
+            ParameterKind::Float { min: lower, max: upper, .. } => {
+                *lower = min as f32;
+                *upper = max as f32;
+            },
+            ParameterKind::Integer { min: lower, max: upper, .. } => {
+                *lower = min as i64;
+                *upper = max as i64;
+            },
+            _ => panic!("Parameter {} doesn't take a range", self.name),
+        }
+        self
+    }
+
+    pub fn default_value(&self) -> Option<ParameterValue> {
+        match self.kind {
+            ParameterKind::Flag => Some(ParameterValue::Bool(false)),
+            ParameterKind::Float { default, .. } => default.map(ParameterValue::Float),
+            ParameterKind::Integer { default, .. } => Some(ParameterValue::Integer(default)),
+            ParameterKind::Seed => None,
+            ParameterKind::Color { default } => default.map(ParameterValue::Color),
+            ParameterKind::Choice { default, .. } => Some(ParameterValue::Choice(default)),
+            ParameterKind::Palette { default } => Some(ParameterValue::Palette(default)),
+        }
+    }
+
+    /// Checks that the value has the right type and lies within the parameter's range.
+    pub fn validate(&self, value: &ParameterValue) -> Result<(), String> {
+        match (self.kind, value) {
+            (ParameterKind::Flag, ParameterValue::Bool(_)) => Ok(()),
+            (ParameterKind::Float { min, max, .. }, ParameterValue::Float(value)) => {
+                if value.is_finite() && *value >= min && *value <= max {
+                    Ok(())
+                } else {
+                    Err(format!("`{}` must lie within [{}, {}], got {}", self.name, min, max, value))
+                }
+            },
+            (ParameterKind::Integer { min, max, .. }, ParameterValue::Integer(value)) => {
+                if *value >= min && *value <= max {
+                    Ok(())
+                } else {
+                    Err(format!("`{}` must lie within [{}, {}], got {}", self.name, min, max, value))
+                }
+            },
+            (ParameterKind::Seed, ParameterValue::Integer(value)) => {
+                if u32::try_from(*value).is_ok() {
+                    Ok(())
+                } else {
+                    Err(format!("`{}` must lie within [0, {}], got {}", self.name, u32::MAX, value))
+                }
+            },
+            (ParameterKind::Color { .. }, ParameterValue::Color(_)) => Ok(()),
+            (ParameterKind::Choice { choices, .. }, ParameterValue::Choice(value)) => {
+                if choices.contains(value) {
+                    Ok(())
+                } else {
+                    Err(format!("`{}` must be one of {}, got \"{}\"", self.name, choices.join(", "), value))
+                }
+            },
+            (ParameterKind::Palette { .. }, ParameterValue::Palette(_)) => Ok(()),
+            (_, value) => Err(format!("`{}` expects {}, got {:?}", self.name, self.type_name(), value)),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self.kind {
+            ParameterKind::Flag => "a boolean",
+            ParameterKind::Float { .. } => "a number",
+            ParameterKind::Integer { .. } | ParameterKind::Seed => "an integer",
+            ParameterKind::Color { .. } => "a hex color like \"#ff8800\"",
+            ParameterKind::Choice { .. } => "a name",
+            ParameterKind::Palette { .. } => "a palette name, cosine palette, list of hex colors or palette file",
+        }
+    }
+
+    /// Builds the command line argument for this parameter.
+    pub fn arg(&self) -> Arg {
+        let arg = Arg::new(self.name)
+            .long(self.name);
+
+        match self.kind {
+            ParameterKind::Flag => arg
+                .help(format!("{} [default: false]", self.description))
+                .action(ArgAction::Set)
+                .num_args(0..=1)
+                .require_equals(true)
+                .default_missing_value("true")
+                .value_parser(value_parser!(bool)),
+            ParameterKind::Float { default: Some(default), .. } => arg
+                .help(format!("{} [default: {}]", self.description, default))
+                .value_parser(value_parser!(f32)),
+            ParameterKind::Float { default: None, .. } => arg
+                .help(self.description)
+                .value_parser(value_parser!(f32)),
+            ParameterKind::Integer { default, .. } => arg
+                .help(format!("{} [default: {}]", self.description, default))
+                .value_parser(value_parser!(i64)),
+            ParameterKind::Seed => arg
+                .help(self.description)
+                .value_parser(value_parser!(u32)),
+            ParameterKind::Color { default: Some(default) } => arg
+                .help(format!("{} [default: {}]", self.description, format_hex_color(default))),
+            ParameterKind::Color { default: None } => arg
+                .help(self.description),
+            ParameterKind::Choice { default, choices } => arg
+                .help(format!("{} [default: {}]", self.description, default))
+                .value_parser(PossibleValuesParser::new(choices)),
+            ParameterKind::Palette { default } => arg
+                .help(format!(
+                    "{}, one of {} or a cosine palette, hex colors or file [default: {}]",
+                    self.description,
+                    Palette::NAMES.join(", "),
+                    default.name().unwrap_or("custom"),
+                )),
+        }
+    }
+
+    /// Reads the value of this parameter from parsed command line arguments, if it was given.
+    fn value_from_matches(&self, matches: &ArgMatches) -> Result<Option<ParameterValue>, String> {
+        let value = match self.kind {
+            ParameterKind::Flag => matches.get_one::<bool>(self.name).map(|x| ParameterValue::Bool(*x)),
+            ParameterKind::Float { .. } => matches.get_one::<f32>(self.name).map(|x| ParameterValue::Float(*x)),
+            ParameterKind::Integer { .. } => matches.get_one::<i64>(self.name).map(|x| ParameterValue::Integer(*x)),
+            ParameterKind::Seed => matches.get_one::<u32>(self.name).map(|x| ParameterValue::Integer(*x as i64)),
+            ParameterKind::Color { .. } => match matches.get_one::<String>(self.name) {
+                Some(hex) => Some(ParameterValue::Color(self.parse_color(hex)?)),
+                None => None,
+            },
+            ParameterKind::Choice { .. } => match matches.get_one::<String>(self.name) {
+                Some(name) => Some(ParameterValue::Choice(self.parse_choice(name)?)),
+                None => None,
+            },
+            ParameterKind::Palette { .. } => match matches.get_one::<String>(self.name) {
+                Some(spec) => Some(ParameterValue::Palette(self.parse_palette(spec)?)),
+                None => None,
+            },
+        };
+
+        Ok(value)
+    }
+
+    fn parse_color(&self, hex: &str) -> Result<Srgb<u8>, String> {
+        parse_hex_color(hex)
+            .ok_or_else(|| format!("`{}` expects {}, got \"{}\"", self.name, self.type_name(), hex))
+    }
+
+    /// Looks up the name among the choices, which gives it a `'static` lifetime.
+    fn parse_choice(&self, name: &str) -> Result<&'static str, String> {
+        match self.kind {
+            ParameterKind::Choice { choices, .. } => choices.iter()
+                .find(|choice| **choice == name)
+                .copied()
+                .ok_or_else(|| format!("`{}` must be one of {}, got \"{}\"", self.name, choices.join(", "), name)),
+            _ => Err(format!("`{}` expects {}, got \"{}\"", self.name, self.type_name(), name)),
+        }
+    }
+
+    fn parse_palette(&self, spec: &str) -> Result<Palette, String> {
+        Palette::parse(spec)
+            .map_err(|e| format!("`{}`: {}", self.name, e))
+    }
+
+    /// Converts a JSON value to a value of this parameter.
+    /// A `null` gives `None` for parameters without a default, e.g. a seed, meaning the scene picks one.
+    fn value_from_json(&self, json: &serde_json::Value) -> Result<Option<ParameterValue>, String> {
+        if self.default_value().is_none() && json.is_null() {
+            return Ok(None);
+        }
+
+        let value = match (self.kind, json) {
+            (ParameterKind::Flag, serde_json::Value::Bool(x)) => Some(ParameterValue::Bool(*x)),
+            (ParameterKind::Float { .. }, serde_json::Value::Number(x)) => x.as_f64().map(|x| ParameterValue::Float(x as f32)),
+            (ParameterKind::Integer { .. } | ParameterKind::Seed, serde_json::Value::Number(x)) => x.as_i64().map(ParameterValue::Integer),
+            (ParameterKind::Color { .. }, serde_json::Value::String(hex)) => Some(ParameterValue::Color(self.parse_color(hex)?)),
+            (ParameterKind::Choice { .. }, serde_json::Value::String(name)) => Some(ParameterValue::Choice(self.parse_choice(name)?)),
+            (ParameterKind::Palette { .. }, serde_json::Value::String(spec)) => Some(ParameterValue::Palette(self.parse_palette(spec)?)),
+            _ => None,
+        };
+
+        value
+            .map(Some)
+            .ok_or_else(|| format!("`{}` expects {}, got {}", self.name, self.type_name(), json))
+    }
+}
+
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterValue {
+    Bool(bool),
+    Float(f32),
+    Integer(i64),
+    Color(Srgb<u8>),
+    Choice(&'static str),
+    Palette(Palette),
+}
+
+
+/// The values for a scene's parameters, keyed by parameter name.
+/// Built from the schema, so every parameter with a default has a value.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ParameterValues {
+    values: BTreeMap<&'static str, ParameterValue>,
+}
+
+impl ParameterValues {
+    /// Creates the values holding the defaults of the given parameters.
+    pub fn defaults(parameters: &[Parameter]) -> Self {
+        let values = parameters.iter()
+            .filter_map(|parameter| parameter.default_value().map(|value| (parameter.name, value)))
+            .collect();
+
+        Self {
+            values,
+        }
+    }
+
+    /// Sets a parameter, checking that it exists and that the value is valid.
+    pub fn set(&mut self, parameters: &[Parameter], name: &str, value: ParameterValue) -> Result<(), String> {
+        let parameter = find_parameter(parameters, name)?;
+        parameter.validate(&value)?;
+        self.values.insert(parameter.name, value);
+
+        Ok(())
+    }
+
+    /// Creates the values from parsed command line arguments, falling back to the defaults.
+    pub fn from_matches(parameters: &[Parameter], matches: &ArgMatches) -> Result<Self, String> {
+        let mut values = Self::defaults(parameters);
+        values.apply_matches(parameters, matches)?;
+
+        Ok(values)
+    }
+
+    /// Overrides the values with those given on the command line.
+    pub fn apply_matches(&mut self, parameters: &[Parameter], matches: &ArgMatches) -> Result<(), String> {
+        for parameter in parameters {
+            if let Some(value) = parameter.value_from_matches(matches)? {
+                self.set(parameters, parameter.name, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overrides the values with all values set in `other`.
+    pub fn extend(&mut self, other: &ParameterValues) {
+        self.values.extend(other.values.iter().map(|(name, value)| (*name, value.clone())));
+    }
+
+    /// Creates the values from a JSON object, falling back to the defaults.
+    pub fn from_json(parameters: &[Parameter], json: &str) -> Result<Self, String> {
+        let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(json)
+            .map_err(|e| format!("Invalid options: {}", e))?;
+
+        Self::from_object(parameters, &object)
+    }
+
+    /// Creates the values from a TOML or JSON config file, falling back to the defaults.
+    /// The format is picked by the file extension.
+    pub fn from_config(parameters: &[Parameter], path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("Can't read config file {}: {}", path.display(), e))?;
+
+        let object: serde_json::Map<String, serde_json::Value> = match path.extension().and_then(|x| x.to_str()) {
+            Some("toml") => toml::from_str(&text)
+                .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+            Some("json") => serde_json::from_str(&text)
+                .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+            _ => return Err(format!("Config file {} should end in .toml or .json", path.display())),
+        };
+
+        Self::from_object(parameters, &object)
+            .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))
+    }
+
+    fn from_object(parameters: &[Parameter], object: &serde_json::Map<String, serde_json::Value>) -> Result<Self, String> {
+        let mut values = Self::defaults(parameters);
+
+        for (name, json_value) in object.iter() {
+            let parameter = find_parameter(parameters, name)?;
+            match parameter.value_from_json(json_value)? {
+                Some(value) => values.set(parameters, name, value)?,
+                None => { values.values.remove(parameter.name); },
+            }
+        }
+
+        Ok(values)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ParameterValue> {
+        self.values.get(name)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        match self.get(name) {
+            Some(ParameterValue::Bool(value)) => *value,
+            value => panic!("Parameter {} is not a flag: {:?}", name, value),
+        }
+    }
+
+    pub fn float(&self, name: &str) -> f32 {
+        match self.get(name) {
+            Some(ParameterValue::Float(value)) => *value,
+            value => panic!("Parameter {} is not a float: {:?}", name, value),
+        }
+    }
+
+    pub fn optional_float(&self, name: &str) -> Option<f32> {
+        match self.get(name) {
+            Some(ParameterValue::Float(value)) => Some(*value),
+            None => None,
+            value => panic!("Parameter {} is not a float: {:?}", name, value),
+        }
+    }
+
+    pub fn integer(&self, name: &str) -> i64 {
+        match self.get(name) {
+            Some(ParameterValue::Integer(value)) => *value,
+            value => panic!("Parameter {} is not an integer: {:?}", name, value),
+        }
+    }
+
+    pub fn color(&self, name: &str) -> Srgb<u8> {
+        match self.get(name) {
+            Some(ParameterValue::Color(value)) => *value,
+            value => panic!("Parameter {} is not a color: {:?}", name, value),
+        }
+    }
+
+    /// A color parameter without a default, `None` unless it was given.
+    pub fn optional_color(&self, name: &str) -> Option<Srgb<u8>> {
+        match self.get(name) {
+            Some(ParameterValue::Color(value)) => Some(*value),
+            None => None,
+            value => panic!("Parameter {} is not a color: {:?}", name, value),
+        }
+    }
+
+    pub fn palette(&self, name: &str) -> Palette {
+        match self.get(name) {
+            Some(ParameterValue::Palette(value)) => *value,
+            value => panic!("Parameter {} is not a palette: {:?}", name, value),
+        }
+    }
+
+    pub fn choice(&self, name: &str) -> &'static str {
+        match self.get(name) {
+            Some(ParameterValue::Choice(value)) => value,
+            value => panic!("Parameter {} is not a choice: {:?}", name, value),
+        }
+    }
+
+    pub fn seed(&self) -> Option<u32> {
+        match self.get("seed") {
+            Some(ParameterValue::Integer(value)) => Some(*value as u32),
+            None => None,
+            value => panic!("Parameter seed is not an integer: {:?}", value),
+        }
+    }
+}
+
+fn find_parameter<'a>(parameters: &'a [Parameter], name: &str) -> Result<&'a Parameter, String> {
+    parameters.iter()
+        .find(|parameter| parameter.name == name)
+        .ok_or_else(|| {
+            let known = parameters.iter().map(|parameter| parameter.name).collect::<Vec<_>>();
+            format!("Unknown parameter `{}`, expected one of: {}", name, known.join(", "))
+        })
+}
+
+/// Parses colors written as `"#rrggbb"`, the `#` being optional.
+pub fn parse_hex_color(hex: &str) -> Option<Srgb<u8>> {
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+    Some(Srgb::new(channel(0)?, channel(2)?, channel(4)?))
+}
+
+pub fn format_hex_color(color: Srgb<u8>) -> String {
+    format!("#{:02x}{:02x}{:02x}", color.red, color.green, color.blue)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use clap::Command;
+    use super::*;
+
+    fn parameters() -> Vec<Parameter> {
+        vec![
+            Parameter::flag("show-vectors", "Shows the vectors"),
+            Parameter::integer("particle-count", "The amount of particles", 100)
+                .with_range(1.0, 1000.0),
+            Parameter::float("noise-scale", "The scale of the noise", 4.0)
+                .with_range(0.001, 1000.0),
+            Parameter::choice("noise", "The noise function", &["perlin", "worley"], "perlin"),
+            Parameter::seed(),
+        ]
+    }
+
+    fn matches(args: &[&str]) -> ArgMatches {
+        Command::new("scene")
+            .args(parameters().iter().map(Parameter::arg))
+            .try_get_matches_from(std::iter::once("scene").chain(args.iter().copied()))
+            .unwrap()
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let error = ParameterValues::from_json(&parameters(), r#"{ "particle-cuont": 10 }"#).unwrap_err();
+
+        assert!(error.contains("Unknown parameter `particle-cuont`"), "{}", error);
+    }
+
+    #[test]
+    fn values_outside_the_range_are_rejected() {
+        for json in [r#"{ "particle-count": 0 }"#, r#"{ "noise-scale": 5000 }"#, r#"{ "seed": -1 }"#, r#"{ "noise": "simplex" }"#] {
+            assert!(ParameterValues::from_json(&parameters(), json).is_err(), "{} was accepted", json);
+        }
+
+        assert!(ParameterValues::defaults(&parameters()).set(&parameters(), "particle-count", ParameterValue::Integer(1001)).is_err());
+    }
+
+    #[test]
+    fn values_of_the_wrong_type_are_rejected() {
+        for json in [r#"{ "show-vectors": 1 }"#, r#"{ "particle-count": 2.5 }"#, r#"{ "noise-scale": "4" }"#, r#"{ "noise": 1 }"#] {
+            let error = ParameterValues::from_json(&parameters(), json).unwrap_err();
+            assert!(error.contains("expects"), "{}: {}", json, error);
+        }
+    }
+
+    #[test]
+    fn command_line_values_override_the_config() {
+        let mut values = ParameterValues::from_json(&parameters(), r#"{ "particle-count": 10, "noise": "worley", "show-vectors": true }"#).unwrap();
+
+        values.apply_matches(&parameters(), &matches(&["--particle-count", "20"])).unwrap();
+
+        assert_eq!(values.integer("particle-count"), 20);
+        assert_eq!(values.choice("noise"), "worley");
+        assert_eq!(values.float("noise-scale"), 4.0);
+        assert!(values.flag("show-vectors"));
+    }
+
+    #[test]
+    fn flags_can_be_turned_off_on_the_command_line() {
+        let config = ParameterValues::from_json(&parameters(), r#"{ "show-vectors": true }"#).unwrap();
+
+        for (args, expected) in [(&[][..], true), (&["--show-vectors=false"][..], false), (&["--show-vectors"][..], true)] {
+            let mut values = config.clone();
+            values.apply_matches(&parameters(), &matches(args)).unwrap();
+
+            assert_eq!(values.flag("show-vectors"), expected, "{:?}", args);
+        }
+
+        let values = ParameterValues::from_matches(&parameters(), &matches(&["--show-vectors=true"])).unwrap();
+        assert!(values.flag("show-vectors"));
+    }
+}
@@ -1,115 +1,234 @@
-#[cfg(not(target_family = "wasm"))]
-use rand::{Rng, thread_rng};
-#[cfg(target_family = "wasm")]
-use rand::{Rng, SeedableRng, rngs::SmallRng};
+use rand::{Rng, SeedableRng};
 
 use std::cell::RefCell;
 
-use nannou::noise::{Perlin, Seedable};
-use nannou::noise;
-use nannou::noise::NoiseFn;
-use nannou::math::num_traits::Float;
+use nannou::noise::Seedable;
 use nannou::prelude::*;
 
 use async_trait::async_trait;
-use clap::Parser;
-use lazy_static::lazy_static;
 use nannou::app::Builder;
 use nannou::color::Alpha;
 use nannou::wgpu::{DeviceDescriptor, Limits};
-use crate::{Args, SceneArgs};
-use crate::particle::{Particle2, scale_coords};
-use crate::scenes::Scene;
-
-
-// Can't inject parameters into the nannou model yet, so we need to use lazy_static
-// to access them as global variables.
-// https://github.com/nannou-org/nannou/issues/793
-lazy_static! {
-    static ref OPTIONS: PerlinFlowOptions = PerlinFlowOptions::from_args(&Args::parse());
-}
-
-#[derive(Copy, Clone, Debug)]
+use serde_json::{json, Value};
+use crate::colormap::{age_fraction, ColorSample, ColormapOptions};
+use crate::flow_grid::FlowGrid;
+use crate::integrator::IntegratorOptions;
+use crate::lifecycle::{BoundaryPolicy, LifecycleOptions};
+use crate::noise_field::{EvolutionOptions, FieldMode, FieldNoise, NoiseField, NoiseOptions};
+use crate::particle::{MAX_VELOCITY, Particle2, scale_coords};
+use crate::plot::{Plottable, Trail};
+use crate::render::{Painter, RenderContext};
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::{ModelInstance, Scene, SceneInstance, WindowOptions};
+use crate::simulation::{SceneRng, seeded_rng, Simulation};
+use crate::snapshot::{check_settings, f64_field, field, rect_field, rect_json, restore_all, restored, settings_json, Snapshot, snapshot_all, SnapshotFile, u32_field};
+use crate::timestep::{FixedTimestep, TimestepOptions};
+use crate::world::{FitMode, WorldOptions, WorldPainter, WorldTransform};
+
+
+#[derive(Clone, Debug)]
 pub struct PerlinFlowScene {
-    // _app_builder: Builder<Model<Perlin>>,
-    model_fn: fn(app: &App) -> Model<Perlin>,
-    update_fn: fn(app: &App, model: &mut Model<Perlin>, _update: Update),
-    view_fn: fn(app: &App, model: &Model<Perlin>, frame: Frame),
-    seed: Option<u32>,
+    update_fn: fn(app: &App, model: &mut Model<NoiseField>, _update: Update),
+    advance_fn: fn(model: &mut Model<NoiseField>, context: &RenderContext),
+    draw_fn: fn(context: &RenderContext, model: &Model<NoiseField>, painter: &mut dyn Painter, opacity: f32),
+    event_fn: fn(app: &App, model: &mut Model<NoiseField>, event: Event),
+    options: PerlinFlowOptions,
+    config: Option<ConfigWatcher>,
+    snapshots: Option<SnapshotFile>,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct PerlinFlowOptions {
     pub show_vectors: bool,
     pub hide_dots: bool,
+    /// The seed for the scene's RNG, a random one is picked when `None`.
     pub seed: Option<u32>,
+    pub particle_count: usize,
+    /// How many noise features fit in the world, higher values give a busier field.
+    pub noise_scale: f64,
+    /// The noise function the field is built from.
+    pub noise: NoiseOptions,
+    /// Whether the noise gives the angle of the flow or its curl does.
+    pub mode: FieldMode,
+    pub evolution: EvolutionOptions,
+    /// Samples the field on a grid with this many cells along each side of the world,
+    /// which particles look their forces up in. The noise is sampled for every particle at 0.
+    pub grid_resolution: usize,
+    /// How the particles follow the forces of the field.
+    pub integrator: IntegratorOptions,
+    /// Particles spawn at most this many world units from the center, horizontally and vertically.
+    pub spawn_extent: f32,
+    /// How long particles live and what happens when they leave the world.
+    pub lifecycle: LifecycleOptions,
+    pub particle_color: Srgb<u8>,
+    /// Colors the particles by an attribute like their angle or speed, instead of the particle color.
+    pub colormap: ColormapOptions,
+    /// The alpha of a particle at 1000 fps, it is scaled by the frame time when drawing.
+    pub particle_alpha: f32,
+    pub vector_color: Srgb<u8>,
+    /// The window is never cleared, so the particles draw their trails onto this color.
+    /// The palette's background is used when it isn't set.
+    pub background_color: Option<Srgb<u8>>,
+    pub window: WindowOptions,
+    /// The area the particles move in, which is drawn onto the window or image however big it is.
+    pub world: WorldOptions,
+    pub timestep: TimestepOptions,
 }
 
-impl PerlinFlowOptions {
-    pub fn from_args(cli_args: &Args) -> Self {
-        match cli_args.scene {
-            SceneArgs::PerlinFlow { show_vectors, hide_dots } => {
-                Self {
-                    show_vectors,
-                    hide_dots,
-                    seed: Some(0),
-                }
+impl Default for PerlinFlowOptions {
+    fn default() -> Self {
+        Self {
+            show_vectors: false,
+            hide_dots: false,
+            seed: None,
+            particle_count: 1000,
+            noise_scale: 4.0,
+            noise: NoiseOptions::default(),
+            mode: FieldMode::Angle,
+            evolution: EvolutionOptions::default(),
+            grid_resolution: 0,
+            integrator: IntegratorOptions::default(),
+            spawn_extent: 300.0,
+            lifecycle: LifecycleOptions::default(),
+            particle_color: WHITE,
+            colormap: ColormapOptions::default(),
+            particle_alpha: 0.0003,
+            vector_color: DARKRED,
+            background_color: None,
+            window: WindowOptions {
+                width: 1800,
+                height: 1200,
+            },
+            world: WorldOptions {
+                width: 1800.0,
+                height: 1200.0,
+                fit: FitMode::Fit,
             },
-            _ => panic!("Can't construct PerlinFlowOptions from this scene type"),
+            timestep: TimestepOptions::default(),
         }
     }
 }
 
+impl PerlinFlowOptions {
+    pub fn parameters() -> Vec<Parameter> {
+        let defaults = Self::default();
+        let mut parameters = vec![
+            Parameter::flag("show-vectors", "Toggles whether to show the direction vectors"),
+            Parameter::flag("hide-dots", "Toggles whether to hide the moving dots"),
+            Parameter::seed(),
+            Parameter::integer("particle-count", "The amount of particles", defaults.particle_count as i64)
+                .with_range(1.0, 1_000_000.0),
+            Parameter::float("noise-scale", "How many noise features fit in the world", defaults.noise_scale as f32)
+                .with_range(0.001, 1000.0),
+            Parameter::float("spawn-extent", "Particles spawn at most this many world units from the center", defaults.spawn_extent)
+                .with_range(1.0, 100_000.0),
+            Parameter::choice("field-mode", "Whether the noise gives the angle of the flow, or its curl gives a flow without sinks", FieldMode::NAMES, defaults.mode.name()),
+            Parameter::integer("grid-resolution", "Samples the field on a grid with this many cells along each side, 0 samples it for every particle", defaults.grid_resolution as i64)
+                .with_range(0.0, 4096.0),
+            Parameter::color("particle-color", "The color of the particles", defaults.particle_color),
+            Parameter::float("particle-alpha", "The alpha of the particles at 1000 fps", defaults.particle_alpha)
+                .with_range(0.0, 1.0),
+            Parameter::color("vector-color", "The color of the direction vectors", defaults.vector_color),
+            Parameter::optional_color("background-color", "The color the particles draw onto, the palette's background if not given"),
+        ];
+        parameters.extend(ColormapOptions::parameters(defaults.colormap));
+        parameters.extend(LifecycleOptions::parameters(defaults.lifecycle));
+        parameters.extend(NoiseOptions::parameters(defaults.noise));
+        parameters.extend(EvolutionOptions::parameters(defaults.evolution));
+        parameters.extend(IntegratorOptions::parameters(defaults.integrator));
+        parameters.extend(WindowOptions::parameters(defaults.window));
+        parameters.extend(WorldOptions::parameters(defaults.world));
+        parameters.extend(TimestepOptions::parameters(defaults.timestep));
+
+        parameters
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            show_vectors: values.flag("show-vectors"),
+            hide_dots: values.flag("hide-dots"),
+            seed: values.seed(),
+            particle_count: values.integer("particle-count") as usize,
+            noise_scale: values.float("noise-scale") as f64,
+            noise: NoiseOptions::from_parameters(values),
+            mode: FieldMode::from_name(values.choice("field-mode")).unwrap(),
+            evolution: EvolutionOptions::from_parameters(values),
+            grid_resolution: values.integer("grid-resolution") as usize,
+            integrator: IntegratorOptions::from_parameters(values),
+            spawn_extent: values.float("spawn-extent"),
+            lifecycle: LifecycleOptions::from_parameters(values),
+            particle_color: values.color("particle-color"),
+            colormap: ColormapOptions::from_parameters(values),
+            particle_alpha: values.float("particle-alpha"),
+            vector_color: values.color("vector-color"),
+            background_color: values.optional_color("background-color"),
+            window: WindowOptions::from_parameters(values),
+            world: WorldOptions::from_parameters(values),
+            timestep: TimestepOptions::from_parameters(values),
+        }
+    }
+
+    /// Whether going from `self` to `other` needs the particles to be respawned,
+    /// the other options can be applied to the running scene.
+    pub fn needs_reset(&self, other: &PerlinFlowOptions) -> bool {
+        self.seed != other.seed
+            || self.particle_count != other.particle_count
+            || self.spawn_extent != other.spawn_extent
+    }
+
+    /// The background color, which comes from the palette unless it is set.
+    pub fn background(&self) -> Srgb<u8> {
+        self.background_color.unwrap_or(self.colormap.palette.background)
+    }
+}
+
 #[async_trait]
 impl Scene for PerlinFlowScene {
     type SceneOptions = PerlinFlowOptions;
-    type Model = Model<Perlin>;
+    type Model = Model<NoiseField>;
 
+    const NAME: &'static str = "perlin-flow";
+    const DESCRIPTION: &'static str = "Particles drifting through a flow field of Perlin or other noise";
 
-    fn new_scene(options: &Self::SceneOptions) -> Self {
-        let seed = options.seed;
+    fn parameters() -> Vec<Parameter> {
+        PerlinFlowOptions::parameters()
+    }
+
+    fn options(values: &ParameterValues) -> Self::SceneOptions {
+        PerlinFlowOptions::from_parameters(values)
+    }
 
+    fn new_scene(options: &Self::SceneOptions) -> Self {
         Self {
-            model_fn: model,
             update_fn: update,
-            view_fn: view,
-            seed,
+            advance_fn: advance,
+            draw_fn: draw,
+            event_fn: event,
+            options: *options,
+            config: None,
+            snapshots: None,
         }
     }
 
-    async fn app(&self) -> Builder<Self::Model> {
-        // #[cfg(not(target_family = "wasm"))]
-        // let seed: u32 = thread_rng().gen();
-        // #[cfg(target_family = "wasm")]
-        // let seed: u32 = 0;
-
-        #[cfg(not(target_family = "wasm"))]
-        let mut rng = thread_rng();
-
-        #[cfg(target_family = "wasm")]
-        let mut rng = SmallRng::seed_from_u64(self.seed.unwrap_or(0b101101101) as u64);
+    fn watch_config(&mut self, watcher: ConfigWatcher) {
+        self.config = Some(watcher);
+    }
 
-        let seed: u32 = rng.gen();
-        let noise_fn = noise::Perlin::new().set_seed(seed);
+    fn snapshot_file(&mut self, file: SnapshotFile) {
+        self.snapshots = Some(file);
+    }
 
-        let model = Model {
-            seed,
-            noise_fn,
-            noise_scale: 4.0,
-            particles: vec![0; 1000].iter().map(|_|
-                Particle2::new(
-                    pt2(
-                        rng.gen_range(-300..300) as f32,
-                        rng.gen_range(-300..300) as f32,
-                    ),
-                    Alpha {
-                        color: WHITE.into_format(),
-                        alpha: 0.0003,
-                    }
-                )
-            ).collect(),
-        };
-        thread_local!(static MODEL: RefCell<Option<Model<Perlin>>> = Default::default());
+    async fn app(&self) -> Result<Builder<Self::Model>, String> {
+        let mut model = Model::new(&self.options);
+        model.config = self.config.clone();
+        if let Some(snapshots) = self.snapshots.clone() {
+            snapshots.resume(&mut model)?;
+            model.snapshots = snapshots;
+        }
+        // The model is built up front and handed over through a thread local,
+        // since the async model function can't capture the scene options.
+        thread_local!(static MODEL: RefCell<Option<Model<NoiseField>>> = Default::default());
         MODEL.with(|m| m.borrow_mut().replace(model));
 
         let builder = app::Builder::new_async(|app| {
@@ -122,27 +241,36 @@ impl Scene for PerlinFlowScene {
                     ..Default::default()
                 };
 
+                let model = MODEL.with(|m| m.borrow_mut().take().unwrap());
+                let window = model.options.window;
+
                 app.new_window()
                     .device_descriptor(device_descriptor)
-                    .view(view::<Perlin>)
+                    .view(view::<NoiseField>)
                     .title("n0ls Perlin Flow")
-                    .size(1800, 1200)
+                    .size(window.width, window.height)
                     .build_async()
                     .await
                     .unwrap();
 
-                MODEL.with(|m| m.borrow_mut().take().unwrap())
+                model
             })
         });
 
-        builder
+        Ok(builder
             .update(self.update_fn)
+            .event(self.event_fn))
+    }
 
-        // Old code
-        // nannou::app(self.model_fn)
-        //     .update(self.update_fn)
-        //     .simple_window(self.view_fn)
-        //     .size(1800, 1200)
+    fn instance(&self) -> Box<dyn SceneInstance> {
+        Box::new(ModelInstance {
+            model: Model::new(&self.options),
+            update_fn: self.update_fn,
+            advance_fn: self.advance_fn,
+            event_fn: self.event_fn,
+            draw_fn: self.draw_fn,
+            background: self.options.background(),
+        })
     }
 }
 
@@ -151,122 +279,472 @@ impl Scene for PerlinFlowScene {
 
 pub struct Model<T>
     where
-        T: NoiseFn<[f64; 2]>
+        T: FieldNoise
+{
+    pub options: PerlinFlowOptions,
+    /// The seed the scene's RNG was created with.
+    pub seed: u32,
+    pub timestep: FixedTimestep,
+    pub simulation: PerlinFlowSimulation<T>,
+    /// The area the scene was last advanced for, which the recorded trails are mapped onto.
+    pub screen: Rect,
+    /// The config file whose changes are applied while running, if any.
+    pub config: Option<ConfigWatcher>,
+    pub snapshots: SnapshotFile,
+}
+
+impl Model<NoiseField> {
+    pub fn new(options: &PerlinFlowOptions) -> Self {
+        let (seed, mut rng) = seeded_rng(options.seed);
+
+        Self {
+            options: *options,
+            seed,
+            timestep: FixedTimestep::new(options.timestep),
+            simulation: PerlinFlowSimulation::new(&mut rng, options, options.world.rect()),
+            screen: options.world.rect(),
+            config: None,
+            snapshots: SnapshotFile::for_scene(PerlinFlowScene::NAME),
+        }
+    }
+
+    /// Applies new options to the running scene.
+    /// The particles are only respawned when a structural option like the seed or particle count changes,
+    /// the rest is applied in place. The window size only takes effect on the next launch.
+    pub fn reconfigure(&mut self, options: &PerlinFlowOptions) {
+        if self.options.needs_reset(options) {
+            let (seed, mut rng) = seeded_rng(options.seed);
+            self.seed = seed;
+            self.simulation = PerlinFlowSimulation::new(&mut rng, options, options.world.rect());
+        } else {
+            self.simulation.bounds = options.world.rect();
+            self.simulation.noise_scale = options.noise_scale;
+            self.simulation.mode = options.mode;
+            self.simulation.evolution = options.evolution;
+            self.simulation.grid_resolution = options.grid_resolution;
+            self.simulation.grid = None;
+            self.simulation.integrator = options.integrator;
+            if self.options.noise != options.noise {
+                self.simulation.noise_fn = NoiseField::new(&options.noise).set_seed(self.simulation.seed);
+            }
+            if self.options.lifecycle != options.lifecycle {
+                self.simulation.lifecycle = options.lifecycle;
+                self.simulation.assign_lifetimes();
+            }
+            for particle in self.simulation.particles.iter_mut() {
+                particle.color = Alpha {
+                    color: options.particle_color.into_format(),
+                    alpha: options.particle_alpha,
+                };
+            }
+        }
+
+        self.timestep.set_options(options.timestep);
+        self.options = *options;
+    }
+}
+
+/// The window-free part of the Perlin flow scene.
+pub struct PerlinFlowSimulation<T>
+    where
+        T: FieldNoise
 {
     pub seed: u32,
     pub noise_fn: T,
     pub noise_scale: f64,
+    pub mode: FieldMode,
+    pub evolution: EvolutionOptions,
+    /// The simulated seconds since the start, which the field evolves with.
+    pub time: f64,
     pub particles: Vec<Particle2>,
+    /// The area the noise field is stretched over and the particles move in, in world units.
+    pub bounds: Rect,
+    /// Particles spawn at most this many world units from the center, which is where they respawn at the emitter.
+    pub spawn_extent: f32,
+    pub lifecycle: LifecycleOptions,
+    /// Picks the lifetimes and the places particles respawn at.
+    pub rng: SceneRng,
+    /// The number of cells along each side of the grid the field is cached on, it isn't cached at 0.
+    pub grid_resolution: usize,
+    /// The field sampled at `grid_time`, recomputed by `refresh_grid` when it goes stale.
+    pub grid: Option<FlowGrid>,
+    pub grid_time: f64,
+    pub integrator: IntegratorOptions,
+    /// When set, the particles record their paths with points at least this far apart.
+    pub trail_spacing: Option<f32>,
 }
 
+impl PerlinFlowSimulation<NoiseField> {
+    pub fn new<R: Rng>(rng: &mut R, options: &PerlinFlowOptions, bounds: Rect) -> Self {
+        let seed: u32 = rng.gen();
+        let noise_fn = NoiseField::new(&options.noise).set_seed(seed);
+        let spawn_extent = options.spawn_extent as i32;
 
-fn model(app: &App) -> Model<Perlin> {
-    #[cfg(not(target_family = "wasm"))]
-    let mut rng = thread_rng();
+        let mut simulation = Self {
+            seed,
+            noise_fn,
+            noise_scale: options.noise_scale,
+            mode: options.mode,
+            evolution: options.evolution,
+            time: 0.0,
+            particles: vec![0; options.particle_count].iter().map(|_|
+                Particle2::new(
+                    pt2(
+                        rng.gen_range(-spawn_extent..spawn_extent) as f32,
+                        rng.gen_range(-spawn_extent..spawn_extent) as f32,
+                    ),
+                    Alpha {
+                        color: options.particle_color.into_format(),
+                        alpha: options.particle_alpha,
+                    }
+                )
+            ).collect(),
+            bounds,
+            spawn_extent: options.spawn_extent,
+            lifecycle: options.lifecycle,
+            rng: SceneRng::seed_from_u64(rng.gen()),
+            grid_resolution: options.grid_resolution,
+            grid: None,
+            grid_time: 0.0,
+            integrator: options.integrator,
+            trail_spacing: None,
+        };
 
-    #[cfg(target_family = "wasm")]
-    let mut rng = SmallRng::seed_from_u64(0b101101101 as u64);
+        simulation.assign_lifetimes();
+        simulation
+    }
+}
 
-    let seed = rng.gen();
-    let noise_fn = noise::Perlin::new().set_seed(seed);
+/// The options aren't restored, a snapshot is loaded into a scene that was started with them.
+/// The ones that change how the particles move are written as settings, and a snapshot taken with others is rejected.
+impl Snapshot for Model<NoiseField> {
+    fn snapshot(&self) -> Value {
+        json!({
+            "settings": self.settings(),
+            "seed": self.seed,
+            "timestep": self.timestep.snapshot(),
+            "simulation": self.simulation.snapshot(),
+        })
+    }
 
-    let win = app.window_rect();
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        check_settings(snapshot, &self.settings())?;
+        let seed = u32_field(snapshot, "seed")?;
+        let timestep = restored(&self.timestep, snapshot, "timestep")?;
+        self.simulation.restore(field(snapshot, "simulation")?)?;
 
-    Model {
-        seed,
-        noise_fn,
-        noise_scale: 4.0,
-        particles: vec![0; 1000].iter().map(|_|
-            Particle2::new(
-                pt2(
-                    rng.gen_range(win.x.start as i32 / 3..win.x.end as i32 / 3) as f32,
-                    rng.gen_range(win.y.start as i32 / 3..win.y.end as i32 / 3) as f32,
-                ),
-                Alpha {
-                    color: WHITE.into_format(),
-                    alpha: 0.0003,
+        self.seed = seed;
+        self.timestep = timestep;
+
+        Ok(())
+    }
+}
+
+impl Model<NoiseField> {
+    fn settings(&self) -> Value {
+        let options = &self.options;
+        settings_json([
+            json!({
+                "field-mode": options.mode.name(),
+                "grid-resolution": options.grid_resolution,
+                "spawn-extent": options.spawn_extent,
+            }),
+            options.noise.settings(),
+            options.evolution.settings(),
+            options.integrator.settings(),
+            options.lifecycle.settings(),
+            options.timestep.settings(),
+        ])
+    }
+}
+
+impl<T> Plottable for Model<T>
+    where
+        T: FieldNoise
+{
+    fn record_trails(&mut self, min_distance: f32) -> bool {
+        self.simulation.trail_spacing = Some(min_distance);
+        for particle in self.simulation.particles.iter_mut() {
+            particle.path.clear();
+            particle.finished_paths.clear();
+            particle.record_position(min_distance);
+        }
+
+        true
+    }
+
+    /// Every particle's paths, the last one ending at its current position, mapped from the world onto the screen.
+    fn trails(&self) -> Vec<Trail> {
+        let transform = WorldTransform::new(self.simulation.bounds, self.screen, self.options.world.fit);
+
+        self.simulation.particles.iter()
+            .flat_map(|particle| {
+                let mut points = particle.path.clone();
+                if points.last() != Some(&particle.position) {
+                    points.push(particle.position);
                 }
-            )
-        ).collect(),
+
+                particle.finished_paths.iter()
+                    .cloned()
+                    .chain(std::iter::once(points))
+                    .map(|points| Trail {
+                        points: points.into_iter().map(|point| transform.to_screen(point)).collect(),
+                        color: particle.color.color.into_format(),
+                    })
+            })
+            .collect()
     }
 }
 
+impl<T> PerlinFlowSimulation<T>
+    where
+        T: FieldNoise
+{
+    /// Gives every particle a new lifetime, at a random point of which it currently is,
+    /// so the particles don't all respawn at once.
+    pub fn assign_lifetimes(&mut self) {
+        for particle in self.particles.iter_mut() {
+            particle.lifetime = self.lifecycle.random_lifetime(&mut self.rng);
+            if let Some(lifetime) = particle.lifetime {
+                particle.age = self.rng.gen_range(0.0..=lifetime);
+            }
+        }
+    }
+
+    /// Where a particle respawns, at the emitter in the middle or anywhere in the world depending on the boundary policy.
+    fn respawn_position(&mut self) -> Point2 {
+        let area = match self.lifecycle.boundary {
+            BoundaryPolicy::RespawnEmitter => Rect::from_w_h(self.spawn_extent * 2.0, self.spawn_extent * 2.0),
+            _ => self.bounds,
+        };
+
+        pt2(
+            self.rng.gen_range(area.left()..=area.right()),
+            self.rng.gen_range(area.bottom()..=area.top()),
+        )
+    }
+
+    /// Colors the particles by the colormap's attribute, unless they keep the particle color.
+    /// Distances are relative to half the diagonal of the bounds, heights to the bounds.
+    pub fn apply_colormap(&mut self, colormap: &ColormapOptions) {
+        let center = self.bounds.xy();
+        let half_diagonal = self.bounds.wh().length() / 2.0;
+
+        for particle in self.particles.iter_mut() {
+            let sample = ColorSample {
+                angle: particle.velocity.y.atan2(particle.velocity.x),
+                speed: particle.velocity.length() / MAX_VELOCITY,
+                age: age_fraction(particle.age, particle.lifetime),
+                distance: particle.position.distance(center) / half_diagonal,
+                height: scale_coords(self.bounds, particle.position).y,
+            };
+
+            if let Some(color) = colormap.color(&sample) {
+                particle.color.color = color;
+            }
+        }
+    }
+
+    /// The force the field pushes a particle at the position with, looked up in the grid if there is one.
+    pub fn force_at(&self, position: Point2) -> Vec2 {
+        match &self.grid {
+            Some(grid) => grid.sample(position),
+            None => self.sample_force(position),
+        }
+    }
+
+    /// Samples the field on the grid again if it is missing or stale,
+    /// because the bounds changed or the evolving field moved on in time.
+    pub fn refresh_grid(&mut self) {
+        if self.grid_resolution == 0 {
+            self.grid = None;
+            return;
+        }
+
+        let stale = match &self.grid {
+            Some(grid) => grid.resolution() != self.grid_resolution
+                || grid.bounds() != self.bounds
+                || (self.evolution.speed != 0.0 && self.grid_time != self.time),
+            None => true,
+        };
+
+        if stale {
+            self.grid = Some(FlowGrid::new(self.grid_resolution, self.bounds, |position| self.sample_force(position)));
+            self.grid_time = self.time;
+        }
+    }
+
+    /// The force of the field at the position, sampled from the noise.
+    pub fn sample_force(&self, position: Point2) -> Vec2 {
+        let move_length = 22.0;
+        let noise_coords = (scale_coords(self.bounds, position).as_f64() * self.noise_scale).to_array();
+
+        match self.mode {
+            FieldMode::Angle => {
+                let number: f64 = self.evolution.sample(&self.noise_fn, noise_coords, self.time);
+                let angle = number as f32 * TAU;
+
+                let unit_vector = vec2(angle.cos(), angle.sin());
+                unit_vector * move_length
+            },
+            FieldMode::Curl => {
+                let [x, y] = self.evolution.curl(&self.noise_fn, noise_coords, self.time);
+
+                // The noise is stretched over the world, differently along each axis in a world that isn't square.
+                // Scaling each component by the stretch of the other axis keeps the flow free of divergence in the world.
+                let size = self.bounds.wh();
+                let stretch = size.min_element();
+                vec2(x as f32 * stretch / size.y, y as f32 * stretch / size.x) * move_length
+            },
+        }
+    }
+}
+
+impl Snapshot for PerlinFlowSimulation<NoiseField> {
+    fn snapshot(&self) -> Value {
+        json!({
+            "noise_seed": self.seed,
+            "noise_scale": self.noise_scale,
+            "time": self.time,
+            "bounds": rect_json(self.bounds),
+            "particles": snapshot_all(&self.particles),
+            "rng": self.rng.snapshot(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let seed = u32_field(snapshot, "noise_seed")?;
+        let noise_scale = f64_field(snapshot, "noise_scale")?;
+        let time = f64_field(snapshot, "time")?;
+        let bounds = rect_field(snapshot, "bounds")?;
+        let particles = restore_all(snapshot, "particles")?;
+        let rng = restored(&self.rng, snapshot, "rng")?;
+
+        self.seed = seed;
+        self.noise_fn = self.noise_fn.clone().set_seed(seed);
+        self.noise_scale = noise_scale;
+        self.time = time;
+        self.bounds = bounds;
+        self.particles = particles;
+        self.grid = None;
+        self.rng = rng;
+
+        Ok(())
+    }
+}
+
+impl<T> Simulation for PerlinFlowSimulation<T>
+    where
+        T: FieldNoise
+{
+    type Particle = Particle2;
+
+    fn step(&mut self, dt: f32) {
+        self.refresh_grid();
+
+        let states = self.particles.iter()
+            .map(|x| x.integrate(&self.integrator, |position| self.force_at(position), dt))
+            .collect::<Vec<_>>();
+        let trail_spacing = self.trail_spacing;
+
+        for (i, (position, velocity)) in states.into_iter().enumerate() {
+            let x = &mut self.particles[i];
+            x.position = position;
+            x.velocity = velocity;
+            x.age += dt;
+
+            if !x.confine(self.bounds, self.lifecycle.boundary) || x.is_expired() {
+                let position = self.respawn_position();
+                let lifetime = self.lifecycle.random_lifetime(&mut self.rng);
+                self.particles[i].respawn(position, lifetime);
+            }
+
+            if let Some(spacing) = trail_spacing {
+                self.particles[i].record_position(spacing);
+            }
+        }
+
+        self.time += dt as f64;
+    }
+
+    fn particles(&self) -> &[Particle2] {
+        &self.particles
+    }
+}
+
+
 fn view<T>(app: &App, model: &Model<T>, frame: Frame)
     where
-        T: NoiseFn<[f64; 2]>
+        T: FieldNoise
+{
+    let mut draw = app.draw();
+
+    // The particles are faint and never cleared, so they build up on the background over time
+    if app.elapsed_frames() < 2 {
+        draw.background().color(model.options.background());
+    }
+
+    self::draw(&RenderContext::from_app(app), model, &mut draw, 1.0);
+    draw.to_frame(app, &frame).unwrap();
+}
+
+fn draw<T>(context: &RenderContext, model: &Model<T>, painter: &mut dyn Painter, opacity: f32)
+    where
+        T: FieldNoise
 {
-    // draw stuff
-    let win = app.window_rect();
-    let draw = app.draw();
-    let time_passed = app.duration.since_prev_update.as_secs_f32();
-
-    // if app.elapsed_frames() < 2 {
-    //     draw.background().color(BLANCHEDALMOND);
-    // }
-    //
-    // draw.background().color(BLANCHEDALMOND);
-    // draw.background().color(Alpha { color: DARKBLUE, alpha: 0.90000001 });
-    // draw.rect().wh(win.wh()).xy(win.xy()).color(Alpha { color: BLANCHEDALMOND, alpha: 0.009 });
+    // draw stuff, in world coordinates
+    let win = model.simulation.bounds;
+    let transform = WorldTransform::new(win, context.bounds, model.options.world.fit);
+    let painter = &mut WorldPainter::new(painter, transform);
+    let time_passed = context.frame_time;
 
     // add circles in a grid
     let grid_size = (20, 20);
     let step_size: Vec2 = win.wh() / vec2(grid_size.0 as f32, grid_size.1 as f32);
 
-    let line_length = 12.0;
-
-    #[cfg(not(target_family = "wasm"))]
-    if OPTIONS.show_vectors {
+    if model.options.show_vectors {
         for grid_x in 0..grid_size.0 {
             for grid_y in 0..grid_size.1 {
                 // Drawing a circle at every grid point.
                 let grid_num = pt2(grid_x as f32, grid_y as f32);
                 let xy = grid_num * step_size + win.bottom_left() + step_size / 2.0;
 
-                let new_xy = xy + force_vector(&model.noise_fn, model.noise_scale, scale_coords(app, xy)) * 10.0;
-                draw.line().start(xy).end(new_xy).color(DARKRED).stroke_weight(1.0);
+                let new_xy = xy + model.simulation.force_at(xy) * 10.0;
+                painter.line(xy, new_xy, 1.0, Alpha { color: model.options.vector_color.into_format(), alpha: opacity });
             }
         }
     }
 
-    #[cfg(not(target_family = "wasm"))]
-    if !OPTIONS.hide_dots {
-        for particle in model.particles.iter() {
-            draw.ellipse().radius(particle.radius).color(Alpha { color: particle.color.color, alpha: particle.color.alpha * time_passed * 1000.0 }).xy(particle.position);
+    if !model.options.hide_dots {
+        for particle in model.simulation.particles.iter() {
+            let fade = particle.fade(model.options.lifecycle.fade_duration);
+            painter.ellipse(particle.position, particle.radius, Alpha { color: particle.color.color, alpha: particle.color.alpha * fade * time_passed * 1000.0 * opacity });
         }
     }
-    #[cfg(target_family = "wasm")]
-    for particle in model.particles.iter() {
-        draw.ellipse().radius(particle.radius).color(Alpha { color: particle.color.color, alpha: particle.color.alpha * time_passed * 1000.0 }).xy(particle.position);
-    }
-
-    draw.to_frame(app, &frame).unwrap();
 }
 
-fn update<T>(app: &App, model: &mut Model<T>, _update: Update)
-    where
-        T: NoiseFn<[f64; 2]> + Clone
-{
-    let time_passed = app.duration.since_prev_update.as_secs_f32();
+fn update(app: &App, model: &mut Model<NoiseField>, _update: Update) {
+    let context = RenderContext::from_app(app);
 
-    let noise_fn = model.noise_fn.clone();
-    model.particles.iter_mut().for_each(|mut x| {
-        let force = force_vector(&noise_fn, model.noise_scale, x.position_scaled(app));
-        x.update(force, time_passed);
-    });
+    if let Some(values) = model.config.as_mut().and_then(|config| config.poll(context.frame_time)) {
+        model.reconfigure(&PerlinFlowOptions::from_parameters(&values));
+    }
+
+    advance(model, &context);
 }
 
-fn force_vector<T>(noise_fn: &T, noise_scale: f64, scaled_coords: Point2) -> Vec2
+fn advance<T>(model: &mut Model<T>, context: &RenderContext)
     where
-        T: NoiseFn<[f64; 2]>
+        T: FieldNoise
 {
-    let move_length = 22.0;
-
-    let number: f64 = noise_fn.get((scaled_coords.as_f64() * noise_scale).to_array());
-    let angle = number as f32 * TAU;
+    model.screen = context.bounds;
+    model.timestep.advance(&mut model.simulation, context.frame_time);
+    model.simulation.apply_colormap(&model.options.colormap);
+}
 
-    let unit_vector = vec2(angle.cos(), angle.sin());
+fn event(_app: &App, model: &mut Model<NoiseField>, event: Event) {
+    model.timestep.event(&event);
 
-    // unit_vector
-    unit_vector * move_length
+    let snapshots = model.snapshots.clone();
+    snapshots.event(model, &event);
 }
-
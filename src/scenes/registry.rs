@@ -0,0 +1,234 @@
+//! The runtime registry of scenes.
+//! The CLI, the `list` subcommand and the wasm entry point are all generated from it.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::Pin;
+
+use clap::{Arg, ArgMatches, Command, value_parser};
+use crate::benchmark::BenchmarkOptions;
+use crate::scenes::base3d::Base3DScene;
+use crate::scenes::config::ConfigWatcher;
+use crate::scenes::curl_flow::CurlFlowScene;
+use crate::plot::{PlotOptions, quantize_colors};
+use crate::render::RenderOptions;
+use crate::scenes::host::HostOptions;
+use crate::scenes::lorenz::{AttractorScene, LorenzScene};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::scenes::perlin_flow::PerlinFlowScene;
+use crate::scenes::{Scene, SceneInstance};
+use crate::snapshot::SnapshotFile;
+
+
+/// A future that runs a scene's nannou app, failing when the app can't be built.
+pub type SceneFuture = Pin<Box<dyn Future<Output = Result<(), String>>>>;
+
+/// A registered scene, with its type erased.
+pub struct SceneEntry {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub parameters: Vec<Parameter>,
+    run_fn: fn(ParameterValues, Option<ConfigWatcher>, Option<SnapshotFile>) -> SceneFuture,
+    instance_fn: fn(&ParameterValues) -> Box<dyn SceneInstance>,
+}
+
+impl SceneEntry {
+    pub fn of<S>() -> Self
+        where
+            S: Scene + 'static
+    {
+        Self {
+            name: S::NAME,
+            description: S::DESCRIPTION,
+            parameters: S::parameters(),
+            run_fn: run_scene::<S>,
+            instance_fn: instantiate_scene::<S>,
+        }
+    }
+
+    /// Builds the scene from the given parameter values and runs it,
+    /// applying the changes to the watched config file if there is one.
+    /// Without a snapshot file the scene saves its snapshots to `<name>-snapshot.json`.
+    pub fn run(&self, values: ParameterValues, config: Option<ConfigWatcher>, snapshot: Option<SnapshotFile>) -> SceneFuture {
+        (self.run_fn)(values, config, snapshot)
+    }
+
+    /// Builds the scene from the given parameter values, to be run inside the host app.
+    pub fn instance(&self, values: &ParameterValues) -> Box<dyn SceneInstance> {
+        (self.instance_fn)(values)
+    }
+
+    /// The CLI subcommand for this scene.
+    /// Besides the parameters it takes a `--config` file, which the other flags override.
+    pub fn command(&self) -> Command {
+        Command::new(self.name)
+            .about(self.description)
+            .arg(Arg::new("config")
+                .long("config")
+                .help("A TOML or JSON file with the scene's parameters, overridden by the other flags")
+                .value_parser(value_parser!(PathBuf)))
+            .arg(Arg::new("snapshot")
+                .long("snapshot")
+                .help("A JSON snapshot to resume from if it exists, saved with F5 and loaded with F9")
+                .value_parser(value_parser!(PathBuf)))
+            .args(self.parameters.iter().map(Parameter::arg))
+    }
+
+    /// Reads the parameter values from parsed command line arguments,
+    /// on top of the config file if one was given.
+    pub fn values_from_matches(&self, matches: &ArgMatches) -> Result<ParameterValues, String> {
+        let mut values = match matches.get_one::<PathBuf>("config") {
+            Some(path) => ParameterValues::from_config(&self.parameters, path)?,
+            None => ParameterValues::defaults(&self.parameters),
+        };
+        values.apply_matches(&self.parameters, matches)?;
+
+        Ok(values)
+    }
+
+    /// The watcher for the `--config` file, if one was given.
+    pub fn config_watcher(&self, matches: &ArgMatches) -> Result<Option<ConfigWatcher>, String> {
+        ConfigWatcher::from_matches(&self.parameters, matches)
+    }
+
+    /// The `--snapshot` file, if one was given.
+    pub fn snapshot_file(&self, matches: &ArgMatches) -> Option<SnapshotFile> {
+        matches.get_one::<PathBuf>("snapshot")
+            .map(|path| SnapshotFile::new(self.name, path.clone()))
+    }
+}
+
+fn run_scene<S>(values: ParameterValues, config: Option<ConfigWatcher>, snapshot: Option<SnapshotFile>) -> SceneFuture
+    where
+        S: Scene + 'static
+{
+    Box::pin(async move {
+        let mut scene = S::new_scene(&S::options(&values));
+        if let Some(watcher) = config {
+            scene.watch_config(watcher);
+        }
+        if let Some(file) = snapshot {
+            scene.snapshot_file(file);
+        }
+        let app = scene.app().await?;
+        app.run();
+
+        Ok(())
+    })
+}
+
+fn instantiate_scene<S>(values: &ParameterValues) -> Box<dyn SceneInstance>
+    where
+        S: Scene + 'static
+{
+    S::new_scene(&S::options(values)).instance()
+}
+
+
+pub struct SceneRegistry {
+    entries: Vec<SceneEntry>,
+}
+
+impl Default for SceneRegistry {
+    /// Creates the registry holding all scenes of this crate.
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register::<PerlinFlowScene>();
+        registry.register::<LorenzScene>();
+        registry.register::<AttractorScene>();
+        registry.register::<CurlFlowScene>();
+        registry.register::<Base3DScene>();
+
+        registry
+    }
+}
+
+impl SceneRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn register<S>(&mut self)
+        where
+            S: Scene + 'static
+    {
+        assert!(self.get(S::NAME).is_none(), "A scene named {} is already registered", S::NAME);
+        self.entries.push(SceneEntry::of::<S>());
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SceneEntry> {
+        self.entries.iter().find(|entry| entry.name == name)
+    }
+
+    pub fn entries(&self) -> &[SceneEntry] {
+        &self.entries
+    }
+
+    pub fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    /// Builds the scene picked by the scene subcommand of `render`,
+    /// resumed from its `--snapshot` if one was given.
+    pub fn instance_from_matches(&self, matches: &ArgMatches) -> Result<Box<dyn SceneInstance>, String> {
+        self.adjusted_instance_from_matches(matches, |_, _| Ok(()))
+    }
+
+    /// Builds the scene picked by the scene subcommand of `export-svg` like `instance_from_matches`,
+    /// with its palette quantized so its colors fit a few pens.
+    pub fn plot_instance_from_matches(&self, matches: &ArgMatches) -> Result<Box<dyn SceneInstance>, String> {
+        self.adjusted_instance_from_matches(matches, quantize_colors)
+    }
+
+    fn adjusted_instance_from_matches(
+        &self,
+        matches: &ArgMatches,
+        adjust: fn(&[Parameter], &mut ParameterValues) -> Result<(), String>,
+    ) -> Result<Box<dyn SceneInstance>, String> {
+        let (name, scene_matches) = matches.subcommand()
+            .ok_or_else(|| "A scene is required".to_string())?;
+        let entry = self.get(name)
+            .ok_or_else(|| format!("Unknown scene `{}`", name))?;
+
+        let mut values = entry.values_from_matches(scene_matches)?;
+        adjust(&entry.parameters, &mut values)?;
+
+        let mut scene = entry.instance(&values);
+        if let Some(snapshots) = entry.snapshot_file(scene_matches) {
+            scene.resume(&snapshots)?;
+        }
+
+        Ok(scene)
+    }
+
+    /// The CLI, with a subcommand per scene, a `list`, a `host`, a `render`, an `export-svg` and a `benchmark` subcommand.
+    pub fn command(&self) -> Command {
+        Command::new("nannou-generative-art")
+            .subcommand_required(true)
+            .subcommand(Command::new("list").about("Lists the available scenes and their parameters"))
+            .subcommand(HostOptions::command())
+            .subcommand(RenderOptions::command()
+                .subcommands(self.entries.iter().map(SceneEntry::command)))
+            .subcommand(PlotOptions::command()
+                .subcommands(self.entries.iter().map(SceneEntry::command)))
+            .subcommand(BenchmarkOptions::command())
+            .subcommands(self.entries.iter().map(SceneEntry::command))
+    }
+
+    /// A human-readable overview of the scenes and their parameters.
+    pub fn describe(&self) -> String {
+        let mut description = String::new();
+
+        for entry in self.entries.iter() {
+            description += &format!("{}\n    {}\n", entry.name, entry.description);
+            for parameter in entry.parameters.iter() {
+                description += &format!("    --{:<16} {}\n", parameter.name, parameter.description);
+            }
+        }
+
+        description
+    }
+}
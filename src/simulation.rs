@@ -0,0 +1,182 @@
+#[cfg(not(target_family = "wasm"))]
+use rand::{Rng, thread_rng};
+use rand::SeedableRng;
+use rand_chacha::ChaCha12Rng;
+use serde_json::{json, Value};
+use crate::snapshot::{field, Snapshot};
+
+/// The window-free core of a scene.
+///
+/// A simulation owns the particles of a scene and knows how to advance them through time,
+/// without needing access to nannou's `App`. This makes it possible to drive scenes from
+/// tests, batch jobs and exporters on machines without a display.
+/// The nannou `Scene::app` builders are thin adapters over a simulation.
+pub trait Simulation {
+    type Particle;
+
+    /// Advances the simulation by `dt` seconds.
+    fn step(&mut self, dt: f32);
+
+    /// Gives read access to the particles being simulated.
+    fn particles(&self) -> &[Self::Particle];
+}
+
+/// The random number generator shared by all scene constructors.
+///
+/// ChaCha12, the algorithm behind `StdRng`, is used instead of `SmallRng`, since the latter picks a different
+/// algorithm on 32-bit targets such as wasm, which would make native and web runs diverge for the same seed.
+/// It is named directly rather than through `StdRng`, which hides its position in the stream that snapshots need.
+pub type SceneRng = ChaCha12Rng;
+
+/// The seed used on wasm when none is given, since there is no OS randomness to fall back on.
+#[cfg(target_family = "wasm")]
+const DEFAULT_WASM_SEED: u32 = 0b101101101;
+
+/// Creates the RNG for a scene.
+/// Without a seed a random one is picked, which is returned so the run can be reproduced.
+pub fn seeded_rng(seed: Option<u32>) -> (u32, SceneRng) {
+    #[cfg(not(target_family = "wasm"))]
+    let seed = seed.unwrap_or_else(|| thread_rng().gen());
+    #[cfg(target_family = "wasm")]
+    let seed = seed.unwrap_or(DEFAULT_WASM_SEED);
+
+    (seed, SceneRng::seed_from_u64(seed as u64))
+}
+
+/// The RNG is written as its seed and how far into its stream it got, so a restored scene draws the same numbers.
+impl Snapshot for SceneRng {
+    fn snapshot(&self) -> Value {
+        json!({
+            "seed": self.get_seed(),
+            "stream": self.get_stream().to_string(),
+            "word_pos": self.get_word_pos().to_string(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let error = || "`seed` should be a list of 32 bytes".to_string();
+        let bytes = field(snapshot, "seed")?
+            .as_array()
+            .filter(|bytes| bytes.len() == 32)
+            .ok_or_else(error)?;
+        let mut seed = [0; 32];
+        for (byte, value) in seed.iter_mut().zip(bytes) {
+            *byte = value.as_u64()
+                .and_then(|x| u8::try_from(x).ok())
+                .ok_or_else(error)?;
+        }
+
+        // Written as strings, since JSON numbers can't hold all 64 and 128 bits
+        let stream = field(snapshot, "stream")?
+            .as_str()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| "`stream` should be an integer in a string".to_string())?;
+        let word_pos = field(snapshot, "word_pos")?
+            .as_str()
+            .and_then(|text| text.parse().ok())
+            .ok_or_else(|| "`word_pos` should be an integer in a string".to_string())?;
+
+        let mut rng = SceneRng::from_seed(seed);
+        rng.set_stream(stream);
+        rng.set_word_pos(word_pos);
+        *self = rng;
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lifecycle::LifecycleOptions;
+    use crate::noise_field::NoiseField;
+    use crate::scenes::perlin_flow::{PerlinFlowOptions, PerlinFlowSimulation};
+    use crate::snapshot::Snapshot;
+
+    const STEPS: usize = 200;
+
+    fn options(seed: Option<u32>) -> PerlinFlowOptions {
+        PerlinFlowOptions {
+            seed,
+            particle_count: 100,
+            ..PerlinFlowOptions::default()
+        }
+    }
+
+    fn simulation(options: &PerlinFlowOptions) -> PerlinFlowSimulation<NoiseField> {
+        let (_, mut rng) = seeded_rng(options.seed);
+        PerlinFlowSimulation::new(&mut rng, options, options.world.rect())
+    }
+
+    /// The bits of every particle's position and velocity.
+    fn bits(simulation: &PerlinFlowSimulation<NoiseField>) -> Vec<[u32; 4]> {
+        simulation.particles().iter()
+            .map(|particle| {
+                let [x, y] = particle.position.to_array().map(f32::to_bits);
+                let [vx, vy] = particle.velocity.to_array().map(f32::to_bits);
+                [x, y, vx, vy]
+            })
+            .collect()
+    }
+
+    /// The bits of every particle's position and velocity after `STEPS` steps.
+    fn run(seed: Option<u32>) -> Vec<[u32; 4]> {
+        let mut simulation = simulation(&options(seed));
+        for _ in 0..STEPS {
+            simulation.step(1.0 / 60.0);
+        }
+
+        bits(&simulation)
+    }
+
+    #[test]
+    fn same_seed_gives_bit_identical_particles() {
+        assert_eq!(run(Some(42)), run(Some(42)));
+    }
+
+    #[test]
+    fn different_seeds_give_different_particles() {
+        assert_ne!(run(Some(42)), run(Some(43)));
+    }
+
+    #[test]
+    fn restored_snapshots_continue_bit_identically() {
+        // Short lifetimes make the particles respawn, which draws from the RNG
+        let options = PerlinFlowOptions {
+            lifecycle: LifecycleOptions {
+                lifetime: 1.0,
+                ..LifecycleOptions::default()
+            },
+            ..options(Some(42))
+        };
+        let mut original = simulation(&options);
+        for _ in 0..STEPS {
+            original.step(1.0 / 60.0);
+        }
+
+        let mut restored = simulation(&PerlinFlowOptions { seed: Some(43), ..options });
+        // Through text, like a snapshot file
+        let snapshot: Value = serde_json::from_str(&original.snapshot().to_string()).unwrap();
+        restored.restore(&snapshot).unwrap();
+        for _ in 0..STEPS {
+            original.step(1.0 / 60.0);
+            restored.step(1.0 / 60.0);
+        }
+
+        assert_eq!(bits(&original), bits(&restored));
+    }
+
+    #[test]
+    fn invalid_snapshots_leave_the_simulation_as_it_was() {
+        let mut original = simulation(&options(Some(42)));
+        let before = bits(&original);
+
+        // Valid up to the RNG, which is read last
+        let mut snapshot = simulation(&options(Some(43))).snapshot();
+        snapshot["rng"] = Value::Null;
+        assert!(original.restore(&snapshot).is_err());
+
+        assert_eq!(bits(&original), before);
+    }
+}
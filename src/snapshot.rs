@@ -0,0 +1,320 @@
+//! Saving and restoring the full state of a running scene, to resume it later or on another machine.
+//!
+//! Snapshots are JSON, and floats are written with enough digits to read back the exact same `f32`.
+
+use std::fs;
+use std::path::PathBuf;
+
+use nannou::color::Alpha;
+use nannou::prelude::*;
+use serde_json::{json, Value};
+
+
+/// State that can be written to and read back from a snapshot.
+pub trait Snapshot {
+    fn snapshot(&self) -> Value;
+
+    /// Overwrites the state with the one in the snapshot.
+    /// The state is left as it was when the snapshot is invalid, so a bad file can't leave a scene half restored.
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String>;
+}
+
+/// The file a scene's snapshots are saved to and loaded from.
+///
+/// Runtime keys:
+/// - `F5` saves a snapshot
+/// - `F9` loads it back
+#[derive(Clone, Debug)]
+pub struct SnapshotFile {
+    /// The name of the scene, so a snapshot isn't loaded into a different scene.
+    pub scene: &'static str,
+    pub path: PathBuf,
+}
+
+impl SnapshotFile {
+    pub fn new(scene: &'static str, path: PathBuf) -> Self {
+        Self {
+            scene,
+            path,
+        }
+    }
+
+    /// The file used when no `--snapshot` was given, in the working directory.
+    pub fn for_scene(scene: &'static str) -> Self {
+        Self::new(scene, PathBuf::from(format!("{}-snapshot.json", scene)))
+    }
+
+    pub fn save<S>(&self, state: &S) -> Result<(), String>
+        where
+            S: Snapshot + ?Sized
+    {
+        let snapshot = json!({
+            "scene": self.scene,
+            "state": state.snapshot(),
+        });
+        let text = serde_json::to_string_pretty(&snapshot)
+            .map_err(|e| format!("Can't write snapshot {}: {}", self.path.display(), e))?;
+
+        fs::write(&self.path, text)
+            .map_err(|e| format!("Can't write snapshot {}: {}", self.path.display(), e))
+    }
+
+    pub fn load<S>(&self, state: &mut S) -> Result<(), String>
+        where
+            S: Snapshot + ?Sized
+    {
+        let text = fs::read_to_string(&self.path)
+            .map_err(|e| format!("Can't read snapshot {}: {}", self.path.display(), e))?;
+        let snapshot: Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid snapshot {}: {}", self.path.display(), e))?;
+
+        let scene = field(&snapshot, "scene")?.as_str();
+        if scene != Some(self.scene) {
+            return Err(format!("Snapshot {} is not of scene `{}`", self.path.display(), self.scene));
+        }
+
+        state.restore(field(&snapshot, "state")?)
+            .map_err(|e| format!("Invalid snapshot {}: {}", self.path.display(), e))
+    }
+
+    /// Loads the snapshot if the file exists, so a scene can be started before its first snapshot is saved.
+    pub fn resume<S>(&self, state: &mut S) -> Result<(), String>
+        where
+            S: Snapshot + ?Sized
+    {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        self.load(state)
+    }
+
+    /// Saves on `F5` and loads on `F9`, reporting the result on stderr.
+    pub fn event<S>(&self, state: &mut S, event: &Event)
+        where
+            S: Snapshot + ?Sized
+    {
+        let result = match event {
+            Event::WindowEvent { simple: Some(KeyPressed(Key::F5)), .. } => self.save(state)
+                .map(|_| format!("Saved snapshot {}", self.path.display())),
+            Event::WindowEvent { simple: Some(KeyPressed(Key::F9)), .. } => self.load(state)
+                .map(|_| format!("Loaded snapshot {}", self.path.display())),
+            _ => return,
+        };
+
+        match result {
+            Ok(message) => eprintln!("{}", message),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+
+pub fn field<'a>(snapshot: &'a Value, name: &str) -> Result<&'a Value, String> {
+    snapshot.get(name)
+        .ok_or_else(|| format!("missing `{}`", name))
+}
+
+pub fn f64_field(snapshot: &Value, name: &str) -> Result<f64, String> {
+    field(snapshot, name)?
+        .as_f64()
+        .ok_or_else(|| format!("`{}` should be a number", name))
+}
+
+pub fn f32_field(snapshot: &Value, name: &str) -> Result<f32, String> {
+    field(snapshot, name)?
+        .as_f64()
+        .map(|x| x as f32)
+        .ok_or_else(|| format!("`{}` should be a number", name))
+}
+
+pub fn u32_field(snapshot: &Value, name: &str) -> Result<u32, String> {
+    field(snapshot, name)?
+        .as_u64()
+        .and_then(|x| u32::try_from(x).ok())
+        .ok_or_else(|| format!("`{}` should be an integer within [0, {}]", name, u32::MAX))
+}
+
+pub fn bool_field(snapshot: &Value, name: &str) -> Result<bool, String> {
+    field(snapshot, name)?
+        .as_bool()
+        .ok_or_else(|| format!("`{}` should be a boolean", name))
+}
+
+fn floats_field<const N: usize>(snapshot: &Value, name: &str) -> Result<[f32; N], String> {
+    floats(field(snapshot, name)?)
+        .ok_or_else(|| format!("`{}` should be a list of {} numbers", name, N))
+}
+
+fn floats<const N: usize>(value: &Value) -> Option<[f32; N]> {
+    let values = value.as_array()
+        .filter(|values| values.len() == N)?;
+
+    let mut floats = [0.0; N];
+    for (float, value) in floats.iter_mut().zip(values) {
+        *float = value.as_f64()? as f32;
+    }
+
+    Some(floats)
+}
+
+pub fn vec2_field(snapshot: &Value, name: &str) -> Result<Vec2, String> {
+    floats_field::<2>(snapshot, name).map(Vec2::from)
+}
+
+pub fn vec3_field(snapshot: &Value, name: &str) -> Result<Vec3, String> {
+    floats_field::<3>(snapshot, name).map(Vec3::from)
+}
+
+/// Reads a list of points written as `[[x, y], ...]`.
+pub fn vec2s_field(snapshot: &Value, name: &str) -> Result<Vec<Vec2>, String> {
+    vec2s(field(snapshot, name)?)
+        .ok_or_else(|| format!("`{}` should be a list of lists of 2 numbers", name))
+}
+
+/// Reads a list of paths written as `[[[x, y], ...], ...]`.
+pub fn paths_field(snapshot: &Value, name: &str) -> Result<Vec<Vec<Vec2>>, String> {
+    field(snapshot, name)?
+        .as_array()
+        .and_then(|paths| paths.iter().map(vec2s).collect())
+        .ok_or_else(|| format!("`{}` should be a list of lists of points", name))
+}
+
+fn vec2s(value: &Value) -> Option<Vec<Vec2>> {
+    value.as_array()?
+        .iter()
+        .map(|value| floats::<2>(value).map(Vec2::from))
+        .collect()
+}
+
+/// Reads a list of points written as `[[x, y, z], ...]`.
+pub fn vec3s_field(snapshot: &Value, name: &str) -> Result<Vec<Vec3>, String> {
+    field(snapshot, name)?
+        .as_array()
+        .and_then(|values| values.iter()
+            .map(|value| floats::<3>(value).map(Vec3::from))
+            .collect())
+        .ok_or_else(|| format!("`{}` should be a list of lists of 3 numbers", name))
+}
+
+/// Reads a color written by `color_json` as `[red, green, blue, alpha]`.
+pub fn color_field(snapshot: &Value, name: &str) -> Result<Alpha<Rgb, f32>, String> {
+    let [red, green, blue, alpha] = floats_field::<4>(snapshot, name)?;
+
+    Ok(Alpha {
+        color: rgb(red, green, blue),
+        alpha,
+    })
+}
+
+/// Reads a rect written by `rect_json` as `[left, right, bottom, top]`.
+pub fn rect_field(snapshot: &Value, name: &str) -> Result<Rect, String> {
+    let [left, right, bottom, top] = floats_field::<4>(snapshot, name)?;
+
+    Ok(Rect::from_corners(pt2(left, bottom), pt2(right, top)))
+}
+
+/// Joins the settings of a scene's option groups, written by parameter name, into one object.
+pub fn settings_json<I: IntoIterator<Item = Value>>(groups: I) -> Value {
+    let mut settings = serde_json::Map::new();
+    for group in groups {
+        if let Value::Object(group) = group {
+            settings.extend(group);
+        }
+    }
+
+    Value::Object(settings)
+}
+
+/// Fails when the snapshot's `settings` differ from the ones the scene runs with.
+/// They come from the scene's options rather than being restored, and the same state moves differently under other ones.
+pub fn check_settings(snapshot: &Value, settings: &Value) -> Result<(), String> {
+    let saved = field(snapshot, "settings")?;
+    let mismatches = settings.as_object()
+        .into_iter()
+        .flatten()
+        .filter(|(name, value)| saved.get(name.as_str()) != Some(value))
+        .map(|(name, value)| format!(
+            "--{} {} instead of {}",
+            name,
+            saved.get(name.as_str()).map_or("nothing".to_string(), setting_text),
+            setting_text(value),
+        ))
+        .collect::<Vec<_>>();
+
+    if mismatches.is_empty() {
+        Ok(())
+    } else {
+        Err(format!("the snapshot was taken with {}, start the scene with the same options to load it", mismatches.join(", ")))
+    }
+}
+
+/// A setting as it would be passed on the command line, without the quotes of strings.
+fn setting_text(value: &Value) -> String {
+    value.as_str()
+        .map_or_else(|| value.to_string(), str::to_string)
+}
+
+pub fn color_json(color: Alpha<Rgb, f32>) -> Value {
+    json!([color.color.red, color.color.green, color.color.blue, color.alpha])
+}
+
+pub fn rect_json(rect: Rect) -> Value {
+    json!([rect.left(), rect.right(), rect.bottom(), rect.top()])
+}
+
+/// Reads the state under `name` into a copy of `state`, for restoring it along with other parts that may still fail.
+pub fn restored<S: Snapshot + Clone>(state: &S, snapshot: &Value, name: &str) -> Result<S, String> {
+    let mut state = state.clone();
+    state.restore(field(snapshot, name)?)?;
+    Ok(state)
+}
+
+pub fn snapshot_all<S: Snapshot>(states: &[S]) -> Value {
+    Value::Array(states.iter().map(Snapshot::snapshot).collect())
+}
+
+/// Reads a list written by `snapshot_all`, which may differ in length from the current one.
+pub fn restore_all<S: Snapshot + Default>(snapshot: &Value, name: &str) -> Result<Vec<S>, String> {
+    let snapshots = field(snapshot, name)?
+        .as_array()
+        .ok_or_else(|| format!("`{}` should be a list", name))?;
+
+    snapshots.iter()
+        .map(|snapshot| {
+            let mut state = S::default();
+            state.restore(snapshot)?;
+            Ok(state)
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Value {
+        settings_json([
+            json!({ "noise": "perlin", "octaves": 4 }),
+            json!({ "step-size": 0.01 }),
+        ])
+    }
+
+    #[test]
+    fn snapshots_with_the_same_settings_load() {
+        let snapshot = json!({ "settings": settings() });
+
+        assert_eq!(check_settings(&snapshot, &settings()), Ok(()));
+    }
+
+    #[test]
+    fn snapshots_with_other_settings_are_rejected() {
+        let snapshot = json!({ "settings": { "noise": "worley", "octaves": 4 } });
+
+        let error = check_settings(&snapshot, &settings()).unwrap_err();
+        assert!(error.contains("--noise worley instead of perlin"), "{}", error);
+        assert!(error.contains("--step-size nothing instead of 0.01"), "{}", error);
+        assert!(check_settings(&json!({}), &settings()).is_err());
+    }
+}
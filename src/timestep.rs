@@ -0,0 +1,158 @@
+//! A fixed-timestep loop for driving simulations independently of the frame rate.
+
+use nannou::prelude::*;
+use serde_json::{json, Value};
+use crate::scenes::parameters::{Parameter, ParameterValues};
+use crate::simulation::Simulation;
+use crate::snapshot::{bool_field, f32_field, Snapshot};
+
+
+/// The factor by which the runtime keys speed up or slow down time.
+const TIME_SCALE_STEP: f32 = 2.0;
+
+#[derive(Copy, Clone, Debug)]
+pub struct TimestepOptions {
+    /// The amount of simulated time per step, in seconds.
+    pub step_size: f32,
+    /// The maximum amount of steps per frame.
+    /// Time beyond that is dropped, so a slow machine doesn't fall further and further behind.
+    pub max_sub_steps: u32,
+    /// How fast simulated time passes compared to wall clock time.
+    pub time_scale: f32,
+}
+
+impl Default for TimestepOptions {
+    fn default() -> Self {
+        Self {
+            step_size: 1.0 / 120.0,
+            max_sub_steps: 32,
+            time_scale: 1.0,
+        }
+    }
+}
+
+impl TimestepOptions {
+    /// The parameters for the timestep, with the scene's defaults.
+    pub fn parameters(defaults: TimestepOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::float("step-size", "The amount of simulated seconds per simulation step", defaults.step_size)
+                .with_range(1.0e-6, 1.0),
+            Parameter::integer("max-sub-steps", "The maximum amount of simulation steps per frame", defaults.max_sub_steps as i64)
+                .with_range(1.0, 10_000.0),
+            Parameter::float("time-scale", "How fast simulated time passes compared to real time", defaults.time_scale)
+                .with_range(0.0, 1000.0),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            step_size: values.float("step-size"),
+            max_sub_steps: values.integer("max-sub-steps") as u32,
+            time_scale: values.float("time-scale"),
+        }
+    }
+
+    /// The options that change the simulation by parameter name, for checking a snapshot against.
+    /// The time scale is part of the snapshot and the amount of sub-steps only limits how far a frame gets.
+    pub fn settings(&self) -> Value {
+        json!({
+            "step-size": self.step_size,
+        })
+    }
+}
+
+/// Accumulates frame time and advances a simulation in steps of exactly `step_size`,
+/// so the result only depends on the amount of time passed, not on the frame rate.
+///
+/// Runtime keys:
+/// - `]` speeds time up, `[` slows it down
+/// - `Backspace` resets the speed
+/// - `Space` pauses and resumes
+#[derive(Copy, Clone, Debug)]
+pub struct FixedTimestep {
+    pub options: TimestepOptions,
+    pub time_scale: f32,
+    pub paused: bool,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(options: TimestepOptions) -> Self {
+        Self {
+            options,
+            time_scale: options.time_scale,
+            paused: false,
+            accumulator: 0.0,
+        }
+    }
+
+    /// Swaps in new options, keeping the time accumulated so far.
+    /// A speed set with the runtime keys is kept unless the options change the time scale.
+    pub fn set_options(&mut self, options: TimestepOptions) {
+        if options.time_scale != self.options.time_scale {
+            self.time_scale = options.time_scale;
+        }
+        self.options = options;
+    }
+
+    /// Advances the simulation by the given amount of wall clock time.
+    /// Returns the amount of steps taken.
+    pub fn advance<S>(&mut self, simulation: &mut S, frame_time: f32) -> u32
+        where
+            S: Simulation + ?Sized
+    {
+        if self.paused {
+            return 0;
+        }
+
+        self.accumulator += frame_time * self.time_scale;
+
+        let mut steps = 0;
+        while self.accumulator >= self.options.step_size && steps < self.options.max_sub_steps {
+            simulation.step(self.options.step_size);
+            self.accumulator -= self.options.step_size;
+            steps += 1;
+        }
+
+        if steps == self.options.max_sub_steps {
+            self.accumulator = self.accumulator.min(self.options.step_size);
+        }
+
+        steps
+    }
+
+    pub fn event(&mut self, event: &Event) {
+        if let Event::WindowEvent { simple: Some(KeyPressed(key)), .. } = event {
+            match key {
+                Key::RBracket => self.time_scale *= TIME_SCALE_STEP,
+                Key::LBracket => self.time_scale /= TIME_SCALE_STEP,
+                Key::Back => self.time_scale = self.options.time_scale,
+                Key::Space => self.paused = !self.paused,
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Only the runtime state is written, the options come from the scene's parameters.
+impl Snapshot for FixedTimestep {
+    fn snapshot(&self) -> Value {
+        json!({
+            "time_scale": self.time_scale,
+            "paused": self.paused,
+            "accumulator": self.accumulator,
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Value) -> Result<(), String> {
+        let time_scale = f32_field(snapshot, "time_scale")?;
+        let paused = bool_field(snapshot, "paused")?;
+        let accumulator = f32_field(snapshot, "accumulator")?;
+
+        self.time_scale = time_scale;
+        self.paused = paused;
+        self.accumulator = accumulator;
+
+        Ok(())
+    }
+}
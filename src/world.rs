@@ -0,0 +1,153 @@
+//! A fixed coordinate system for scenes to simulate in, mapped onto whatever area they are drawn on,
+//! so the same artwork comes out at any window size or export resolution.
+
+use nannou::prelude::*;
+use crate::render::Painter;
+use crate::scenes::parameters::{Parameter, ParameterValues};
+
+
+/// How the world is mapped onto a screen with a different aspect ratio.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FitMode {
+    /// The whole world is shown, with bars of background along the sides that are too long.
+    Fit,
+    /// The whole screen is covered, cutting off the world along the sides that are too long.
+    Fill,
+    /// The world is scaled differently along each axis to cover the screen exactly.
+    Stretch,
+}
+
+impl FitMode {
+    pub const ALL: [FitMode; 3] = [
+        FitMode::Fit,
+        FitMode::Fill,
+        FitMode::Stretch,
+    ];
+
+    /// The names of all modes, in the order of `ALL`.
+    pub const NAMES: &'static [&'static str] = &[
+        "fit",
+        "fill",
+        "stretch",
+    ];
+
+    pub fn name(&self) -> &'static str {
+        let index = Self::ALL.iter().position(|mode| mode == self).unwrap();
+        Self::NAMES[index]
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES.iter()
+            .position(|known| *known == name)
+            .map(|index| Self::ALL[index])
+    }
+}
+
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WorldOptions {
+    /// The size of the world in world units, which are pixels when it is drawn at the same size.
+    pub width: f32,
+    pub height: f32,
+    pub fit: FitMode,
+}
+
+impl WorldOptions {
+    /// The parameters for the world, with the scene's defaults.
+    pub fn parameters(defaults: WorldOptions) -> Vec<Parameter> {
+        vec![
+            Parameter::float("world-width", "The width of the world the scene is simulated in", defaults.width)
+                .with_range(1.0, 100_000.0),
+            Parameter::float("world-height", "The height of the world the scene is simulated in", defaults.height)
+                .with_range(1.0, 100_000.0),
+            Parameter::choice("world-fit", "How the world is fit onto a window or image with another aspect ratio", FitMode::NAMES, defaults.fit.name()),
+        ]
+    }
+
+    pub fn from_parameters(values: &ParameterValues) -> Self {
+        Self {
+            width: values.float("world-width"),
+            height: values.float("world-height"),
+            fit: FitMode::from_name(values.choice("world-fit")).unwrap(),
+        }
+    }
+
+    /// The world's area, centered on the origin.
+    pub fn rect(&self) -> Rect {
+        Rect::from_w_h(self.width, self.height)
+    }
+}
+
+
+/// Maps world coordinates to screen coordinates, with the center of the world at the center of the screen.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WorldTransform {
+    scale: Vec2,
+    offset: Vec2,
+}
+
+impl WorldTransform {
+    pub fn new(world: Rect, screen: Rect, fit: FitMode) -> Self {
+        let ratio = screen.wh() / world.wh();
+        let scale = match fit {
+            FitMode::Fit => Vec2::splat(ratio.min_element()),
+            FitMode::Fill => Vec2::splat(ratio.max_element()),
+            FitMode::Stretch => ratio,
+        };
+
+        Self {
+            scale,
+            offset: screen.xy() - world.xy() * scale,
+        }
+    }
+
+    pub fn to_screen(&self, point: Point2) -> Point2 {
+        point * self.scale + self.offset
+    }
+
+    /// The factor sizes like radii and stroke weights are scaled by,
+    /// between the scales of both axes when the world is stretched.
+    pub fn length_scale(&self) -> f32 {
+        (self.scale.x * self.scale.y).sqrt()
+    }
+}
+
+
+/// Takes world coordinates and paints them in screen coordinates with the painter it wraps.
+pub struct WorldPainter<'a> {
+    painter: &'a mut dyn Painter,
+    transform: WorldTransform,
+}
+
+impl<'a> WorldPainter<'a> {
+    pub fn new(painter: &'a mut dyn Painter, transform: WorldTransform) -> Self {
+        Self {
+            painter,
+            transform,
+        }
+    }
+}
+
+impl<'a> Painter for WorldPainter<'a> {
+    fn clear(&mut self, color: Srgba) {
+        self.painter.clear(color);
+    }
+
+    fn ellipse(&mut self, center: Point2, radius: f32, color: Srgba) {
+        self.painter.ellipse(self.transform.to_screen(center), radius * self.transform.length_scale(), color);
+    }
+
+    fn line(&mut self, start: Point2, end: Point2, weight: f32, color: Srgba) {
+        self.painter.line(
+            self.transform.to_screen(start),
+            self.transform.to_screen(end),
+            weight * self.transform.length_scale(),
+            color,
+        );
+    }
+
+    fn rect(&mut self, rect: Rect, color: Srgba) {
+        let corners = Rect::from_corners(self.transform.to_screen(rect.bottom_left()), self.transform.to_screen(rect.top_right()));
+        self.painter.rect(corners, color);
+    }
+}